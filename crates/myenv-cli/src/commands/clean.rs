@@ -0,0 +1,73 @@
+//! Clean command implementation.
+
+use super::{SchemaOptions, load_manifest};
+use crate::output::{OutputFormat, print_json};
+use myenv_core::{CleanOptions, CleanResult, clean_configs};
+use std::path::Path;
+use std::process::ExitCode;
+
+pub fn run(
+    path: &Path,
+    profile: Option<&str>,
+    options: &CleanOptions,
+    output: OutputFormat,
+    schema: &SchemaOptions,
+) -> ExitCode {
+    let manifest = match load_manifest(path, profile) {
+        Ok(m) => m,
+        Err(e) => {
+            eprintln!("error: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let base_dir = path.parent().unwrap_or(Path::new("."));
+    let provider = schema.provider(Some(&manifest), base_dir);
+    let results = match clean_configs(&manifest, &provider, base_dir, options) {
+        Ok(results) => results,
+        Err(e) => {
+            eprintln!("error: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if output.is_json() {
+        print_json(&results);
+        return ExitCode::SUCCESS;
+    }
+
+    let verb = if options.dry_run {
+        "would remove"
+    } else {
+        "removed"
+    };
+    let mut removed = 0;
+    let mut kept = 0;
+    for result in &results {
+        match result {
+            CleanResult::Removed { tool, path } => {
+                println!("{verb}: {tool} -> {}", path.display());
+                removed += 1;
+            }
+            CleanResult::Edited { tool, path } => {
+                eprintln!(
+                    "warning: kept '{tool}': {} was edited since it was generated",
+                    path.display()
+                );
+                kept += 1;
+            }
+        }
+    }
+
+    if removed == 0 && kept == 0 {
+        println!("nothing to clean");
+    } else if removed > 0 {
+        println!("{verb} {removed} config(s)");
+    }
+    if kept > 0 {
+        eprintln!(
+            "hint: run 'myenv config pull' to keep the edits, or pass --force to remove them"
+        );
+    }
+    ExitCode::SUCCESS
+}
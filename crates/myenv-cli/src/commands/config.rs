@@ -1,20 +1,43 @@
 //! Config sync command implementations.
 
-use myenv_core::{CliSchemaProvider, Manifest, merge_to_manifest, pull_configs};
+use super::{SchemaOptions, load_manifest};
+use crate::output::{OutputFormat, print_json};
+use myenv_core::{SyncResult, merge_to_manifest, pull_configs, sync_configs};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::ExitCode;
 
-pub fn pull(path: &PathBuf, tools: Vec<String>, dry_run: bool) -> ExitCode {
-    let provider = CliSchemaProvider;
-    let base_dir = path.parent().unwrap_or(std::path::Path::new("."));
+pub fn pull(
+    path: &PathBuf,
+    tools: Vec<String>,
+    profile: Option<&str>,
+    dry_run: bool,
+    output: OutputFormat,
+    schema: &SchemaOptions,
+) -> ExitCode {
+    let base_dir = path.parent().unwrap_or(Path::new("."));
+
+    // The existing manifest supplies templates to restore in pulled values.
+    // Only a missing one is fine; a broken one would lose them silently.
+    let manifest = if path.exists() {
+        match load_manifest(path, profile) {
+            Ok(m) => Some(m),
+            Err(e) => {
+                eprintln!("error: {e}");
+                return ExitCode::FAILURE;
+            }
+        }
+    } else {
+        None
+    };
+    let provider = schema.provider(manifest.as_ref(), base_dir);
 
     // Determine which tools to pull
     let tool_names: Vec<String> = if tools.is_empty() {
         // Try to read existing manifest to get tool list
-        match Manifest::from_path(path) {
-            Ok(m) => m.tool_configs.keys().cloned().collect(),
-            Err(_) => {
+        match &manifest {
+            Some(m) => m.tool_configs.keys().cloned().collect(),
+            None => {
                 eprintln!("error: no tools specified and no existing manifest");
                 eprintln!("hint: specify tools to pull, e.g., 'myenv config pull siphon dew'");
                 return ExitCode::FAILURE;
@@ -25,12 +48,16 @@ pub fn pull(path: &PathBuf, tools: Vec<String>, dry_run: bool) -> ExitCode {
     };
 
     if tool_names.is_empty() {
-        println!("no tools to pull");
+        if output.is_json() {
+            print_json(&serde_json::json!({ "pulled": [] }));
+        } else {
+            println!("no tools to pull");
+        }
         return ExitCode::SUCCESS;
     }
 
     // Pull configs
-    let pulled = match pull_configs(&tool_names, &provider, base_dir) {
+    let pulled = match pull_configs(&tool_names, manifest.as_ref(), &provider, base_dir) {
         Ok(p) => p,
         Err(e) => {
             eprintln!("error: {e}");
@@ -38,8 +65,10 @@ pub fn pull(path: &PathBuf, tools: Vec<String>, dry_run: bool) -> ExitCode {
         }
     };
 
-    for config in &pulled {
-        println!("pulled: {} <- {}", config.tool, config.path.display());
+    if !output.is_json() {
+        for config in &pulled {
+            println!("pulled: {} <- {}", config.tool, config.path.display());
+        }
     }
 
     // Merge into manifest
@@ -52,18 +81,119 @@ pub fn pull(path: &PathBuf, tools: Vec<String>, dry_run: bool) -> ExitCode {
         }
     };
 
-    if dry_run {
+    if dry_run && !output.is_json() {
         println!("\n--- nursery.toml (dry run) ---");
         println!("{merged}");
         return ExitCode::SUCCESS;
     }
 
     // Write manifest
-    if let Err(e) = fs::write(path, &merged) {
+    if !dry_run && let Err(e) = fs::write(path, &merged) {
         eprintln!("error: failed to write manifest: {e}");
         return ExitCode::FAILURE;
     }
 
+    if output.is_json() {
+        print_json(&serde_json::json!({
+            "pulled": pulled,
+            "manifest": merged,
+            "updated": !dry_run,
+        }));
+        return ExitCode::SUCCESS;
+    }
+
     println!("updated: {}", path.display());
     ExitCode::SUCCESS
 }
+
+pub fn sync(
+    path: &Path,
+    profile: Option<&str>,
+    dry_run: bool,
+    output: OutputFormat,
+    schema: &SchemaOptions,
+) -> ExitCode {
+    let manifest = match load_manifest(path, profile) {
+        Ok(m) => m,
+        Err(e) => {
+            eprintln!("error: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let base_dir = path.parent().unwrap_or(Path::new("."));
+    let provider = schema.provider(Some(&manifest), base_dir);
+    let report = match sync_configs(&manifest, path, &provider, base_dir, dry_run) {
+        Ok(r) => r,
+        Err(e) => {
+            eprintln!("error: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if output.is_json() {
+        print_json(&report);
+        let conflicted = report
+            .results
+            .iter()
+            .any(|result| matches!(result, SyncResult::Synced(sync) if !sync.conflicts.is_empty()));
+        return if conflicted {
+            ExitCode::FAILURE
+        } else {
+            ExitCode::SUCCESS
+        };
+    }
+
+    let mut conflicts = 0;
+    for result in &report.results {
+        match result {
+            SyncResult::Synced(sync) => {
+                if sync.is_unchanged() {
+                    println!("in sync: {}", sync.tool);
+                }
+                for key in &sync.pushed {
+                    println!("pushed: {key} -> {}", sync.path.display());
+                }
+                for key in &sync.pulled {
+                    println!("pulled: {key} <- {}", sync.path.display());
+                }
+                for conflict in &sync.conflicts {
+                    eprintln!(
+                        "conflict: {} (manifest: {}, tool: {})",
+                        conflict.path,
+                        show(conflict.manifest.as_ref()),
+                        show(conflict.tool.as_ref())
+                    );
+                    conflicts += 1;
+                }
+            }
+            SyncResult::Skipped { tool, reason } => {
+                eprintln!("warning: skipped '{tool}': {reason}");
+            }
+        }
+    }
+
+    if let Some(updated) = &report.manifest {
+        if dry_run {
+            println!("\n--- {} (dry run) ---", path.display());
+            println!("{updated}");
+        } else {
+            println!("updated: {}", path.display());
+        }
+    }
+
+    if conflicts > 0 {
+        eprintln!("{conflicts} conflicting key(s) left unchanged");
+        eprintln!("hint: make both sides agree, then run 'myenv config sync' again");
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
+
+/// Render one side of a conflict.
+fn show(value: Option<impl std::fmt::Display>) -> String {
+    match value {
+        Some(v) => v.to_string(),
+        None => "(removed)".to_string(),
+    }
+}
@@ -1,16 +1,29 @@
 //! Generate command implementation.
 
+use super::{SchemaOptions, load_manifest};
+use crate::output::{OutputFormat, print_json};
 use myenv_core::{
-    CliSchemaProvider, GenerateResult, Manifest, SchemaProvider, generate_configs, preview_configs,
+    DEFAULT_CONTEXT, DiffLine, GenerateError, GenerateOptions, GenerateResult, Manifest,
+    PreviewResult, SchemaProvider, ValidationOutcome, diff_lines, generate_configs,
+    preview_configs, validate_configs,
 };
 use notify::{Config, RecommendedWatcher, RecursiveMode, Watcher};
-use std::path::PathBuf;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
 use std::process::ExitCode;
 use std::sync::mpsc;
 use std::time::Duration;
 
-pub fn run(path: &PathBuf, check_only: bool, diff_mode: bool) -> ExitCode {
-    let manifest = match Manifest::from_path(path) {
+pub fn run(
+    path: &Path,
+    profile: Option<&str>,
+    options: &GenerateOptions,
+    check_only: bool,
+    diff: Option<&DiffOutput>,
+    output: OutputFormat,
+    schema: &SchemaOptions,
+) -> ExitCode {
+    let manifest = match load_manifest(path, profile) {
         Ok(m) => m,
         Err(e) => {
             eprintln!("error: {e}");
@@ -19,124 +32,312 @@ pub fn run(path: &PathBuf, check_only: bool, diff_mode: bool) -> ExitCode {
     };
 
     if manifest.tool_configs.is_empty() {
-        println!("no tools configured");
+        if output.is_json() {
+            print_json(&[(); 0]);
+        } else {
+            println!("no tools configured");
+        }
         return ExitCode::SUCCESS;
     }
 
-    let provider = CliSchemaProvider;
     let base_dir = path.parent().unwrap_or(std::path::Path::new("."));
+    let provider = schema.provider(Some(&manifest), base_dir);
 
     if check_only {
-        // Just validate, don't write
-        for tool_name in manifest.tool_configs.keys() {
-            match provider.fetch(tool_name) {
-                Ok(schema) => {
-                    println!("validated: {tool_name} -> {}", schema.config_path.display());
-                }
-                Err(e) => {
-                    eprintln!("error: {tool_name}: {e}");
-                    return ExitCode::FAILURE;
-                }
-            }
-        }
-        println!("all tools validated");
-        return ExitCode::SUCCESS;
+        return check(&manifest, &provider, options, output);
     }
 
-    if diff_mode {
+    if let Some(output) = diff {
         // Show what would change
-        match preview_configs(&manifest, &provider, base_dir) {
-            Ok(previews) => {
-                let mut has_changes = false;
-                for preview in &previews {
-                    let changed = match &preview.existing {
-                        Some(existing) => existing != &preview.content,
-                        None => true,
-                    };
-
-                    if changed {
-                        has_changes = true;
-                        println!("--- {}", preview.path.display());
-                        print_diff(&preview.existing, &preview.content);
-                        println!();
-                    } else {
-                        println!("unchanged: {} -> {}", preview.tool, preview.path.display());
-                    }
-                }
-                if !has_changes {
-                    println!("no changes");
-                }
-                ExitCode::SUCCESS
-            }
+        return match preview_configs(&manifest, &provider, base_dir, options) {
+            Ok(previews) => show_diff(&previews, output),
             Err(e) => {
                 eprintln!("error: {e}");
+                ExitCode::from(2)
+            }
+        };
+    }
+
+    match generate_configs(&manifest, &provider, base_dir, options) {
+        Ok(results) if output.is_json() => {
+            print_json(&results);
+            if results.iter().any(GenerateResult::is_failed) {
                 ExitCode::FAILURE
+            } else {
+                ExitCode::SUCCESS
             }
         }
-    } else {
-        match generate_configs(&manifest, &provider, base_dir) {
-            Ok(results) => {
-                let mut generated = 0;
-                let mut skipped = 0;
-                for result in &results {
-                    match result {
-                        GenerateResult::Generated(config) => {
-                            println!("generated: {} -> {}", config.tool, config.path.display());
-                            generated += 1;
-                        }
-                        GenerateResult::Skipped { tool, reason } => {
-                            eprintln!("warning: skipped '{tool}': {reason}");
-                            skipped += 1;
-                        }
+        Ok(results) => {
+            let mut generated = 0;
+            let mut skipped = 0;
+            let mut failed = 0;
+            for result in &results {
+                match result {
+                    GenerateResult::Generated(config) => {
+                        println!("generated: {} -> {}", config.tool, config.path.display());
+                        generated += 1;
+                    }
+                    GenerateResult::Skipped { tool, reason } => {
+                        eprintln!("warning: skipped '{tool}': {reason}");
+                        skipped += 1;
+                    }
+                    GenerateResult::Pruned { tool, path } => {
+                        println!("removed: {tool} -> {}", path.display());
+                    }
+                    GenerateResult::Failed { error, .. } => {
+                        report_error(error);
+                        failed += 1;
                     }
                 }
-                if generated > 0 {
-                    println!("generated {} config(s)", generated);
-                }
-                if skipped > 0 {
-                    println!("skipped {} tool(s)", skipped);
-                }
-                ExitCode::SUCCESS
             }
-            Err(e) => {
-                eprintln!("error: {e}");
-                ExitCode::FAILURE
+            if generated > 0 {
+                println!("generated {} config(s)", generated);
             }
+            if skipped > 0 {
+                println!("skipped {} tool(s)", skipped);
+            }
+            if failed > 0 {
+                eprintln!("failed {} tool(s)", failed);
+                return ExitCode::FAILURE;
+            }
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            report_error(&e);
+            ExitCode::FAILURE
         }
     }
 }
 
-/// Print a simple line-based diff.
-fn print_diff(old: &Option<String>, new: &str) {
-    let old_lines: Vec<&str> = old.as_deref().unwrap_or("").lines().collect();
-    let new_lines: Vec<&str> = new.lines().collect();
+/// Print a generation error, with a hint where there's an obvious fix.
+fn report_error(e: &GenerateError) {
+    eprintln!("error: {e}");
+    if let GenerateError::ManuallyEdited { tool, .. } = e {
+        eprintln!(
+            "hint: run 'myenv config pull {tool}' to keep the edits, or pass --force to overwrite them"
+        );
+    }
+}
 
-    if old.is_none() {
-        println!("+++ (new file)");
-        for line in &new_lines {
-            println!("+{line}");
-        }
-        return;
+/// Validate every tool config without writing, reporting all failures.
+fn check(
+    manifest: &Manifest,
+    provider: &dyn SchemaProvider,
+    options: &GenerateOptions,
+    output: OutputFormat,
+) -> ExitCode {
+    if let Err(e) = options.check_selection(manifest) {
+        eprintln!("error: {e}");
+        return ExitCode::FAILURE;
     }
+    let results = validate_configs(manifest, provider, options);
 
-    // Simple diff: show removed lines, then added lines
-    // For a more sophisticated diff, we'd use a diff library
-    for line in &old_lines {
-        if !new_lines.contains(line) {
-            println!("-{line}");
+    if output.is_json() {
+        let report: Vec<_> = results
+            .iter()
+            .map(|result| {
+                let (issues, error) = match &result.outcome {
+                    ValidationOutcome::Valid => (&[][..], None),
+                    ValidationOutcome::Invalid(issues) => (&issues[..], None),
+                    ValidationOutcome::SchemaUnavailable(e) => (&[][..], Some(e.to_string())),
+                };
+                serde_json::json!({
+                    "tool": result.tool,
+                    "config_path": result.config_path,
+                    "valid": result.is_valid(),
+                    "issues": issues,
+                    "error": error,
+                })
+            })
+            .collect();
+        print_json(&report);
+        return if results.iter().all(|r| r.is_valid()) {
+            ExitCode::SUCCESS
+        } else {
+            ExitCode::FAILURE
+        };
+    }
+
+    let mut failed = 0;
+    for result in &results {
+        match &result.outcome {
+            ValidationOutcome::Valid => {
+                let path = result
+                    .config_path
+                    .as_ref()
+                    .map(|p| p.display().to_string())
+                    .unwrap_or_default();
+                println!("validated: {} -> {path}", result.tool);
+            }
+            ValidationOutcome::Invalid(issues) => {
+                failed += 1;
+                eprintln!("error: {}: config is invalid", result.tool);
+                for issue in issues {
+                    eprintln!("  - {}: {}", issue.path, issue.message);
+                }
+            }
+            ValidationOutcome::SchemaUnavailable(e) => {
+                failed += 1;
+                eprintln!("error: {}: {e}", result.tool);
+            }
         }
     }
-    for line in &new_lines {
-        if !old_lines.contains(line) {
-            println!("+{line}");
+
+    if failed > 0 {
+        eprintln!("{failed} of {} tool(s) failed validation", results.len());
+        return ExitCode::FAILURE;
+    }
+
+    println!("all tools validated");
+    ExitCode::SUCCESS
+}
+
+/// How `generate --diff` prints changes.
+#[derive(Debug, Clone, Copy, Default, clap::ValueEnum)]
+pub enum DiffFormat {
+    /// Unified diff, as produced by `diff -u`.
+    #[default]
+    Unified,
+    /// JSON array of per-tool hunks, for tooling.
+    Json,
+}
+
+/// Output settings for `generate --diff`.
+#[derive(Debug, Clone, Copy)]
+pub struct DiffOutput {
+    pub format: DiffFormat,
+    pub color: bool,
+}
+
+impl DiffOutput {
+    /// Resolve `--color`: `auto` colors only when stdout is a terminal and
+    /// `NO_COLOR` is unset.
+    pub fn new(format: DiffFormat, color: clap::ColorChoice) -> Self {
+        let color = match color {
+            clap::ColorChoice::Always => true,
+            clap::ColorChoice::Never => false,
+            clap::ColorChoice::Auto => {
+                std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none()
+            }
+        };
+        Self { format, color }
+    }
+}
+
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const CYAN: &str = "\x1b[36m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+/// Print the changes `generate` would make. Exits with 1 if any config
+/// would change, like `diff`, or 2 if any tool couldn't be previewed.
+fn show_diff(previews: &[PreviewResult], output: &DiffOutput) -> ExitCode {
+    let mut has_changes = false;
+    let mut has_errors = false;
+    let mut report = Vec::new();
+
+    for preview in previews {
+        let preview = match preview {
+            PreviewResult::Preview(preview) => preview,
+            PreviewResult::Failed { tool, error } => {
+                has_errors = true;
+                match output.format {
+                    DiffFormat::Json => report.push(serde_json::json!({
+                        "tool": tool,
+                        "status": "error",
+                        "error": error.to_string(),
+                    })),
+                    DiffFormat::Unified => report_error(error),
+                }
+                continue;
+            }
+        };
+        let old = preview.existing.as_deref().unwrap_or("");
+        let hunks = diff_lines(old, &preview.content, DEFAULT_CONTEXT);
+        let status = match &preview.existing {
+            None => "added",
+            Some(existing) if *existing == preview.content => "unchanged",
+            Some(_) => "modified",
+        };
+        has_changes |= status != "unchanged";
+
+        match output.format {
+            DiffFormat::Json => report.push(serde_json::json!({
+                "tool": preview.tool,
+                "path": preview.path,
+                "status": status,
+                "hunks": hunks,
+            })),
+            DiffFormat::Unified if status == "unchanged" => {
+                println!("unchanged: {} -> {}", preview.tool, preview.path.display());
+            }
+            DiffFormat::Unified => {
+                let old_path = match &preview.existing {
+                    Some(_) => preview.path.display().to_string(),
+                    None => "/dev/null".to_string(),
+                };
+                let paint = |style: &str, text: &str| {
+                    if output.color {
+                        format!("{style}{text}{RESET}")
+                    } else {
+                        text.to_string()
+                    }
+                };
+                println!("{}", paint(BOLD, &format!("--- {old_path}")));
+                println!(
+                    "{}",
+                    paint(BOLD, &format!("+++ {}", preview.path.display()))
+                );
+                for hunk in &hunks {
+                    println!("{}", paint(CYAN, &hunk.header()));
+                    for line in &hunk.lines {
+                        let style = match line {
+                            DiffLine::Context(_) | DiffLine::NoNewline => "",
+                            DiffLine::Removed(_) => RED,
+                            DiffLine::Added(_) => GREEN,
+                        };
+                        println!("{}", paint(style, &line.to_string()));
+                    }
+                }
+            }
         }
     }
+
+    match output.format {
+        DiffFormat::Json => print_json(&report),
+        DiffFormat::Unified if !has_changes && !has_errors => println!("no changes"),
+        DiffFormat::Unified => {}
+    }
+
+    if has_errors {
+        ExitCode::from(2)
+    } else if has_changes {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
 }
 
-pub fn watch(path: &PathBuf) -> ExitCode {
+pub fn watch(
+    path: &PathBuf,
+    profile: Option<&str>,
+    options: &GenerateOptions,
+    output: OutputFormat,
+    schema: &SchemaOptions,
+) -> ExitCode {
+    // Progress goes to stderr in JSON mode, so stdout stays one document per run
+    let status = |message: &str| {
+        if output.is_json() {
+            eprintln!("{message}");
+        } else {
+            println!("{message}");
+        }
+    };
+
     // Run initial generation
-    println!("watching: {}", path.display());
-    if run(path, false, false) == ExitCode::FAILURE {
+    status(&format!("watching: {}", path.display()));
+    if run(path, profile, options, false, None, output, schema) == ExitCode::FAILURE {
         eprintln!("initial generation failed, continuing to watch...");
     }
 
@@ -157,13 +358,14 @@ pub fn watch(path: &PathBuf) -> ExitCode {
         }
     };
 
-    // Watch the manifest file
+    // Watch the manifest file and anything it includes
     if let Err(e) = watcher.watch(path, RecursiveMode::NonRecursive) {
         eprintln!("error: failed to watch {}: {e}", path.display());
         return ExitCode::FAILURE;
     }
+    watch_includes(&mut watcher, path);
 
-    println!("press Ctrl+C to stop");
+    status("press Ctrl+C to stop");
 
     // Debounce: wait a short time after events to batch rapid changes
     let debounce = Duration::from_millis(100);
@@ -178,10 +380,11 @@ pub fn watch(path: &PathBuf) -> ExitCode {
                 }
                 last_event = now;
 
-                println!("\ndetected change, regenerating...");
-                if run(path, false, false) == ExitCode::FAILURE {
+                status("\ndetected change, regenerating...");
+                if run(path, profile, options, false, None, output, schema) == ExitCode::FAILURE {
                     eprintln!("generation failed");
                 }
+                watch_includes(&mut watcher, path);
             }
             Err(e) => {
                 eprintln!("error: watcher error: {e}");
@@ -190,3 +393,15 @@ pub fn watch(path: &PathBuf) -> ExitCode {
         }
     }
 }
+
+/// Watch every file included by the manifest (includes may change between runs).
+fn watch_includes(watcher: &mut RecommendedWatcher, path: &PathBuf) {
+    let Ok(manifest) = Manifest::from_path(path) else {
+        return;
+    };
+    for file in manifest.files.iter().skip(1) {
+        if let Err(e) = watcher.watch(file, RecursiveMode::NonRecursive) {
+            eprintln!("warning: failed to watch {}: {e}", file.display());
+        }
+    }
+}
@@ -1,5 +1,6 @@
 //! Init command implementation.
 
+use crate::output::{OutputFormat, print_json};
 use myenv_seed::{SeedResolver, VariableResolver};
 use std::collections::HashMap;
 use std::io::{self, Write};
@@ -12,6 +13,7 @@ pub fn run(
     cli_vars: Vec<(String, String)>,
     raw: bool,
     no_prompt: bool,
+    output: OutputFormat,
 ) -> ExitCode {
     let resolver = SeedResolver::new();
 
@@ -56,7 +58,7 @@ pub fn run(
                 }
 
                 // Prompt for missing variable
-                match prompt_variable(&missing) {
+                match prompt_variable(&missing, output) {
                     Ok(value) => {
                         let mut cli_map: HashMap<String, String> = HashMap::new();
                         cli_map.insert("name".to_string(), name.to_string());
@@ -88,6 +90,14 @@ pub fn run(
     let dest = PathBuf::from(name);
 
     match seed.scaffold(&dest, &vars, raw) {
+        Ok(()) if output.is_json() => {
+            print_json(&serde_json::json!({
+                "name": name,
+                "seed": seed_name,
+                "path": dest,
+            }));
+            ExitCode::SUCCESS
+        }
         Ok(()) => {
             println!("created project '{name}' from seed '{seed_name}'");
             ExitCode::SUCCESS
@@ -99,9 +109,15 @@ pub fn run(
     }
 }
 
-fn prompt_variable(name: &str) -> io::Result<String> {
-    print!("{name}: ");
-    io::stdout().flush()?;
+/// Prompt on stdout, or on stderr when stdout carries JSON.
+fn prompt_variable(name: &str, output: OutputFormat) -> io::Result<String> {
+    if output.is_json() {
+        eprint!("{name}: ");
+        io::stderr().flush()?;
+    } else {
+        print!("{name}: ");
+        io::stdout().flush()?;
+    }
 
     let mut value = String::new();
     io::stdin().read_line(&mut value)?;
@@ -1,7 +1,53 @@
 //! CLI command implementations.
 
+pub mod clean;
 pub mod config;
 pub mod generate;
 pub mod init;
+pub mod schema;
 pub mod seeds;
 pub mod tools;
+
+use myenv_core::{
+    CachingSchemaProvider, ChainSchemaProvider, CliSchemaProvider, Manifest, ManifestError,
+    SchemaProvider,
+};
+use std::path::Path;
+use std::time::Duration;
+
+/// Load a manifest with an optional profile applied.
+pub fn load_manifest(path: &Path, profile: Option<&str>) -> Result<Manifest, ManifestError> {
+    let manifest = Manifest::from_path(path)?;
+    match profile {
+        Some(name) => manifest.with_profile(name),
+        None => Ok(manifest),
+    }
+}
+
+/// How commands fetch tool schemas.
+#[derive(Debug, Clone, Copy)]
+pub struct SchemaOptions {
+    /// Seconds each tool gets to print its schema (0 waits forever).
+    pub timeout: u64,
+    /// Reuse schemas cached from earlier runs.
+    pub cache: bool,
+}
+
+impl SchemaOptions {
+    /// The schema sources configured in `[myenv]`, with tool binaries run
+    /// per these options and each tool's `bin` and `schema_args`.
+    pub fn provider(&self, manifest: Option<&Manifest>, base_dir: &Path) -> ChainSchemaProvider {
+        let timeout = (self.timeout > 0).then(|| Duration::from_secs(self.timeout));
+        let mut cli = CliSchemaProvider::new().with_timeout(timeout);
+        if let Some(manifest) = manifest {
+            cli = cli.with_tool_overrides(manifest, base_dir);
+        }
+        let cli: Box<dyn SchemaProvider> = if self.cache {
+            Box::new(CachingSchemaProvider::new(cli))
+        } else {
+            Box::new(cli)
+        };
+        let settings = manifest.map(|m| m.settings.clone()).unwrap_or_default();
+        ChainSchemaProvider::from_settings(&settings, base_dir, cli)
+    }
+}
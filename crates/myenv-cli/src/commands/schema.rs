@@ -0,0 +1,24 @@
+//! Schema command implementations.
+
+use crate::output::{OutputFormat, print_json};
+use myenv_core::{CachingSchemaProvider, CliSchemaProvider};
+use std::process::ExitCode;
+
+pub fn clear_cache(output: OutputFormat) -> ExitCode {
+    let cache = CachingSchemaProvider::new(CliSchemaProvider::new());
+
+    if let Err(e) = cache.clear_cache() {
+        eprintln!(
+            "error: failed to clear {}: {e}",
+            cache.cache_dir().display()
+        );
+        return ExitCode::FAILURE;
+    }
+
+    if output.is_json() {
+        print_json(&serde_json::json!({ "cleared": cache.cache_dir() }));
+    } else {
+        println!("cleared: {}", cache.cache_dir().display());
+    }
+    ExitCode::SUCCESS
+}
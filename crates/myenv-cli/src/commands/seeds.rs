@@ -1,12 +1,26 @@
 //! Seeds command implementation.
 
+use crate::output::{OutputFormat, print_json};
 use myenv_seed::SeedResolver;
 use std::process::ExitCode;
 
-pub fn run() -> ExitCode {
+pub fn run(output: OutputFormat) -> ExitCode {
     let resolver = SeedResolver::new();
 
     match resolver.list() {
+        Ok(seeds) if output.is_json() => {
+            let seeds: Vec<_> = seeds
+                .iter()
+                .map(|seed| {
+                    serde_json::json!({
+                        "name": seed.name,
+                        "description": seed.description,
+                    })
+                })
+                .collect();
+            print_json(&seeds);
+            ExitCode::SUCCESS
+        }
         Ok(seeds) => {
             println!("Available seeds:");
             for seed in seeds {
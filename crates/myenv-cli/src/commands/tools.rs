@@ -1,5 +1,6 @@
 //! Tools command implementations.
 
+use crate::output::{OutputFormat, print_json};
 use myenv_core::{
     Ecosystem, LockedPackage, LockedTool, Lockfile, Manifest, RepologyClient, ToolDep,
     detect_ecosystems, detect_primary_ecosystem, is_installed,
@@ -7,11 +8,20 @@ use myenv_core::{
 use std::collections::BTreeMap;
 use std::io::{self, Write};
 use std::path::PathBuf;
-use std::process::ExitCode;
+use std::process::{ExitCode, Stdio};
 
-pub fn ecosystems() -> ExitCode {
+pub fn ecosystems(output: OutputFormat) -> ExitCode {
     let ecosystems = detect_ecosystems();
 
+    if output.is_json() {
+        let detected: Vec<_> = ecosystems.iter().map(|eco| eco.id()).collect();
+        print_json(&serde_json::json!({
+            "detected": detected,
+            "primary": detect_primary_ecosystem().map(|eco| eco.id()),
+        }));
+        return ExitCode::SUCCESS;
+    }
+
     if ecosystems.is_empty() {
         println!("no supported package managers detected");
         return ExitCode::SUCCESS;
@@ -29,7 +39,12 @@ pub fn ecosystems() -> ExitCode {
     ExitCode::SUCCESS
 }
 
-pub fn check(manifest_path: &PathBuf, include_dev: bool, include_build: bool) -> ExitCode {
+pub fn check(
+    manifest_path: &PathBuf,
+    include_dev: bool,
+    include_build: bool,
+    output: OutputFormat,
+) -> ExitCode {
     let manifest = match Manifest::from_path(manifest_path) {
         Ok(m) => m,
         Err(e) => {
@@ -52,10 +67,11 @@ pub fn check(manifest_path: &PathBuf, include_dev: bool, include_build: bool) ->
 
     let mut all_ok = true;
     let mut missing = Vec::new();
+    let mut report = Vec::new();
 
     // Helper to check a set of deps
     let mut check_deps = |deps: &BTreeMap<String, ToolDep>, section: &str| {
-        if !deps.is_empty() {
+        if !deps.is_empty() && !output.is_json() {
             println!("\n[{section}]");
         }
         for (tool_name, dep) in deps {
@@ -75,7 +91,17 @@ pub fn check(manifest_path: &PathBuf, include_dev: bool, include_build: bool) ->
             let status = if installed { "OK" } else { "MISSING" };
             let optional = if dep.optional { " (optional)" } else { "" };
 
-            println!("  {tool_name}: {status}{optional}");
+            if output.is_json() {
+                report.push(serde_json::json!({
+                    "section": section,
+                    "tool": tool_name,
+                    "package": package_name,
+                    "installed": installed,
+                    "optional": dep.optional,
+                }));
+            } else {
+                println!("  {tool_name}: {status}{optional}");
+            }
 
             if !installed && !dep.optional {
                 all_ok = false;
@@ -97,6 +123,19 @@ pub fn check(manifest_path: &PathBuf, include_dev: bool, include_build: bool) ->
         check_deps(&manifest.build_deps, "build-deps");
     }
 
+    if output.is_json() {
+        print_json(&serde_json::json!({
+            "ecosystem": ecosystem.id(),
+            "deps": report,
+            "missing": missing,
+        }));
+        return if all_ok {
+            ExitCode::SUCCESS
+        } else {
+            ExitCode::FAILURE
+        };
+    }
+
     if manifest.tool_deps.is_empty()
         && (!include_dev || manifest.dev_tool_deps.is_empty())
         && (!include_build || manifest.build_deps.is_empty())
@@ -120,6 +159,7 @@ pub fn install(
     dry_run: bool,
     include_dev: bool,
     include_build: bool,
+    output: OutputFormat,
 ) -> ExitCode {
     let manifest = match Manifest::from_path(manifest_path) {
         Ok(m) => m,
@@ -182,29 +222,55 @@ pub fn install(
     missing.sort();
     missing.dedup();
 
+    let packages: Vec<&str> = missing.iter().map(|s| s.as_str()).collect();
+    let cmd = ecosystem.install_cmd(&packages);
+    let report = |installed: bool| {
+        print_json(&serde_json::json!({
+            "ecosystem": ecosystem.id(),
+            "missing": missing,
+            "command": (!missing.is_empty()).then_some(&cmd),
+            "installed": installed,
+        }));
+    };
+
     if missing.is_empty() {
-        println!("all required dependencies already installed");
+        if output.is_json() {
+            report(false);
+        } else {
+            println!("all required dependencies already installed");
+        }
         return ExitCode::SUCCESS;
     }
 
-    let packages: Vec<&str> = missing.iter().map(|s| s.as_str()).collect();
-    let cmd_display = ecosystem.install_cmd_display(&packages);
+    if output.is_json() {
+        if dry_run {
+            report(false);
+            return ExitCode::SUCCESS;
+        }
+    } else {
+        let cmd_display = ecosystem.install_cmd_display(&packages);
 
-    println!("Missing dependencies for {}:", ecosystem.id());
-    for pkg in &missing {
-        println!("  {pkg}");
-    }
-    println!("\nRun this command?\n");
-    println!("  {cmd_display}");
+        println!("Missing dependencies for {}:", ecosystem.id());
+        for pkg in &missing {
+            println!("  {pkg}");
+        }
+        println!("\nRun this command?\n");
+        println!("  {cmd_display}");
 
-    if dry_run {
-        println!("\n(dry run, not executing)");
-        return ExitCode::SUCCESS;
+        if dry_run {
+            println!("\n(dry run, not executing)");
+            return ExitCode::SUCCESS;
+        }
     }
 
-    // Prompt for confirmation
-    print!("\n[Y/n] ");
-    io::stdout().flush().unwrap();
+    // Prompt for confirmation (on stderr when stdout carries JSON)
+    if output.is_json() {
+        eprint!("install {}? [Y/n] ", missing.join(" "));
+        io::stderr().flush().unwrap();
+    } else {
+        print!("\n[Y/n] ");
+        io::stdout().flush().unwrap();
+    }
 
     let mut input = String::new();
     if io::stdin().read_line(&mut input).is_err() {
@@ -214,15 +280,37 @@ pub fn install(
 
     let input = input.trim().to_lowercase();
     if !input.is_empty() && input != "y" && input != "yes" {
-        println!("cancelled");
+        if output.is_json() {
+            report(false);
+        } else {
+            println!("cancelled");
+        }
         return ExitCode::SUCCESS;
     }
 
     // Execute install command
-    let cmd = ecosystem.install_cmd(&packages);
-    println!("\nrunning: {}\n", cmd.join(" "));
+    let mut command = std::process::Command::new(&cmd[0]);
+    command.args(&cmd[1..]);
+    if output.is_json() {
+        // Keep the installer's output off stdout
+        command.stdout(Stdio::from(io::stderr()));
+    } else {
+        println!("\nrunning: {}\n", cmd.join(" "));
+    }
 
-    let status = std::process::Command::new(&cmd[0]).args(&cmd[1..]).status();
+    let status = command.status();
+    if output.is_json() {
+        let installed = status.as_ref().is_ok_and(|s| s.success());
+        if let Err(e) = &status {
+            eprintln!("failed to run command: {e}");
+        }
+        report(installed);
+        return if installed {
+            ExitCode::SUCCESS
+        } else {
+            ExitCode::FAILURE
+        };
+    }
 
     match status {
         Ok(s) if s.success() => {
@@ -240,12 +328,30 @@ pub fn install(
     }
 }
 
-pub fn lookup(tool: &str) -> ExitCode {
+pub fn lookup(tool: &str, output: OutputFormat) -> ExitCode {
     let client = RepologyClient::new();
 
-    println!("Looking up '{tool}' via Repology...\n");
+    if !output.is_json() {
+        println!("Looking up '{tool}' via Repology...\n");
+    }
 
     match client.lookup(tool) {
+        Ok(info) if output.is_json() => {
+            let packages: BTreeMap<_, _> = info
+                .packages
+                .iter()
+                .map(|(ecosystem, pkg)| {
+                    let pkg = serde_json::json!({ "name": pkg.name, "version": pkg.version });
+                    (ecosystem.id(), pkg)
+                })
+                .collect();
+            print_json(&serde_json::json!({
+                "tool": tool,
+                "binname": info.binname,
+                "packages": packages,
+            }));
+            ExitCode::SUCCESS
+        }
         Ok(info) => {
             if info.packages.is_empty() {
                 println!("No packages found for '{tool}'");
@@ -271,7 +377,7 @@ pub fn lookup(tool: &str) -> ExitCode {
     }
 }
 
-pub fn lock(manifest_path: &PathBuf) -> ExitCode {
+pub fn lock(manifest_path: &PathBuf, output: OutputFormat) -> ExitCode {
     let manifest = match Manifest::from_path(manifest_path) {
         Ok(m) => m,
         Err(e) => {
@@ -284,7 +390,11 @@ pub fn lock(manifest_path: &PathBuf) -> ExitCode {
         manifest.tool_deps.len() + manifest.dev_tool_deps.len() + manifest.build_deps.len();
 
     if total_deps == 0 {
-        println!("no dependencies to lock");
+        if output.is_json() {
+            print_json(&serde_json::json!({ "results": [], "tools": {} }));
+        } else {
+            println!("no dependencies to lock");
+        }
         return ExitCode::SUCCESS;
     }
 
@@ -307,27 +417,36 @@ pub fn lock(manifest_path: &PathBuf) -> ExitCode {
         return ExitCode::FAILURE;
     }
 
-    println!(
-        "Resolving dependencies for ecosystems: {}",
-        ecosystems
-            .iter()
-            .map(|e| e.id())
-            .collect::<Vec<_>>()
-            .join(", ")
-    );
+    if !output.is_json() {
+        println!(
+            "Resolving dependencies for ecosystems: {}",
+            ecosystems
+                .iter()
+                .map(|e| e.id())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+
+    let mut results = Vec::new();
 
     // Helper to lock a set of deps
     let mut lock_deps = |deps: &BTreeMap<String, ToolDep>, section: &str| {
         if deps.is_empty() {
             return;
         }
-        println!("\n[{section}]");
+        if !output.is_json() {
+            println!("\n[{section}]");
+        }
 
         for (tool_name, dep) in deps {
-            print!("  {tool_name}... ");
-            io::stdout().flush().unwrap();
+            if !output.is_json() {
+                print!("  {tool_name}... ");
+                io::stdout().flush().unwrap();
+            }
 
-            match client.lookup(tool_name) {
+            // Status for JSON output, and the line printed for text output
+            let (status, message) = match client.lookup(tool_name) {
                 Ok(info) => {
                     let mut eco_packages = BTreeMap::new();
 
@@ -359,9 +478,9 @@ pub fn lock(manifest_path: &PathBuf) -> ExitCode {
                     }
 
                     if eco_packages.is_empty() {
-                        println!("not found");
+                        ("not_found", "not found".to_string())
                     } else {
-                        println!("ok ({} ecosystem(s))", eco_packages.len());
+                        let message = format!("ok ({} ecosystem(s))", eco_packages.len());
 
                         lockfile.tools.insert(
                             tool_name.clone(),
@@ -371,6 +490,7 @@ pub fn lock(manifest_path: &PathBuf) -> ExitCode {
                                 ecosystems: eco_packages,
                             },
                         );
+                        ("locked", message)
                     }
                 }
                 Err(e) => {
@@ -392,7 +512,6 @@ pub fn lock(manifest_path: &PathBuf) -> ExitCode {
                             }
                         }
                         if !eco_packages.is_empty() {
-                            println!("ok (override)");
                             lockfile.tools.insert(
                                 tool_name.clone(),
                                 LockedTool {
@@ -401,13 +520,25 @@ pub fn lock(manifest_path: &PathBuf) -> ExitCode {
                                     ecosystems: eco_packages,
                                 },
                             );
+                            ("override", "ok (override)".to_string())
                         } else {
-                            println!("error: {e}");
+                            ("error", format!("error: {e}"))
                         }
                     } else {
-                        println!("error: {e}");
+                        ("error", format!("error: {e}"))
                     }
                 }
+            };
+
+            if output.is_json() {
+                results.push(serde_json::json!({
+                    "section": section,
+                    "tool": tool_name,
+                    "status": status,
+                    "message": message,
+                }));
+            } else {
+                println!("{message}");
             }
         }
     };
@@ -421,6 +552,14 @@ pub fn lock(manifest_path: &PathBuf) -> ExitCode {
     let lockfile_path = manifest_path.with_file_name("myenv.lock");
 
     match lockfile.write(&lockfile_path) {
+        Ok(()) if output.is_json() => {
+            print_json(&serde_json::json!({
+                "lockfile": lockfile_path,
+                "results": results,
+                "tools": lockfile.tools,
+            }));
+            ExitCode::SUCCESS
+        }
         Ok(()) => {
             println!("\nWrote {}", lockfile_path.display());
             println!("Locked {} dependency(ies)", lockfile.tools.len());
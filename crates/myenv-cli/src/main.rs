@@ -1,6 +1,9 @@
 mod commands;
+mod output;
 
 use clap::{Parser, Subcommand};
+use myenv_core::{CleanOptions, DEFAULT_SCHEMA_TIMEOUT, GenerateOptions};
+use output::OutputFormat;
 use std::path::PathBuf;
 use std::process::ExitCode;
 
@@ -8,6 +11,24 @@ use std::process::ExitCode;
 #[command(name = "myenv")]
 #[command(about = "A configuration manager for the rhi ecosystem")]
 struct Cli {
+    /// Output format for command results
+    #[arg(long, global = true, value_enum, default_value_t)]
+    output: OutputFormat,
+
+    /// Seconds each tool gets to print its schema (0 waits forever)
+    #[arg(
+        long,
+        global = true,
+        env = "MYENV_SCHEMA_TIMEOUT",
+        value_name = "SECONDS",
+        default_value_t = DEFAULT_SCHEMA_TIMEOUT.as_secs()
+    )]
+    schema_timeout: u64,
+
+    /// Always run `<tool> --schema` instead of reusing cached schemas
+    #[arg(long, global = true)]
+    no_schema_cache: bool,
+
     #[command(subcommand)]
     command: Command,
 }
@@ -16,21 +37,75 @@ struct Cli {
 enum Command {
     /// Generate per-tool config files from nursery.toml
     Generate {
+        /// Only generate these tools (default: every tool in the manifest)
+        tools: Vec<String>,
+
+        /// Skip a tool (can be repeated)
+        #[arg(long, value_name = "TOOL")]
+        exclude: Vec<String>,
+
         /// Path to the manifest file
         #[arg(short, long, default_value = "nursery.toml")]
         manifest: PathBuf,
 
+        /// Apply a `[profile.<name>]` overlay from the manifest
+        #[arg(long, env = "MYENV_PROFILE")]
+        profile: Option<String>,
+
         /// Only validate, don't write files
         #[arg(long)]
         check: bool,
 
-        /// Show what would change without writing
+        /// Show what would change without writing (exits 1 if anything would)
         #[arg(long)]
         diff: bool,
 
+        /// Output format for --diff
+        #[arg(long, value_enum, default_value_t, requires = "diff")]
+        diff_format: commands::generate::DiffFormat,
+
+        /// Color --diff output
+        #[arg(long, value_enum, default_value_t)]
+        color: clap::ColorChoice,
+
         /// Watch for changes and regenerate
         #[arg(long)]
         watch: bool,
+
+        /// Leave undefined {{...}} placeholders in place instead of failing
+        #[arg(long)]
+        allow_undefined: bool,
+
+        /// Overwrite configs that were edited since they were last generated
+        #[arg(long)]
+        force: bool,
+
+        /// Keep generating other tools after one fails, then report every failure
+        #[arg(short, long)]
+        keep_going: bool,
+    },
+
+    /// Remove config files written by generate
+    Clean {
+        /// Path to the manifest file
+        #[arg(short, long, default_value = "nursery.toml")]
+        manifest: PathBuf,
+
+        /// Apply a `[profile.<name>]` overlay from the manifest
+        #[arg(long, env = "MYENV_PROFILE")]
+        profile: Option<String>,
+
+        /// List what would be removed without removing anything
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Only remove configs for tools no longer in the manifest
+        #[arg(long)]
+        stale: bool,
+
+        /// Also remove configs that were edited since they were generated
+        #[arg(long)]
+        force: bool,
     },
 
     /// Sync configs between nursery.toml and tool config files
@@ -64,6 +139,12 @@ enum Command {
     /// List available seed templates
     Seeds,
 
+    /// Manage tool schemas
+    Schema {
+        #[command(subcommand)]
+        action: SchemaAction,
+    },
+
     /// Manage tool dependencies
     Tools {
         #[command(subcommand)]
@@ -124,13 +205,54 @@ enum ToolsAction {
     },
 }
 
+#[derive(Subcommand)]
+enum SchemaAction {
+    /// Manage the schema cache
+    Cache {
+        #[command(subcommand)]
+        action: CacheAction,
+    },
+}
+
+#[derive(Subcommand)]
+enum CacheAction {
+    /// Remove every cached schema
+    Clear,
+}
+
 #[derive(Subcommand)]
 enum ConfigAction {
     /// Push nursery.toml to tool config files (alias for generate)
     Push {
+        /// Only push these tools (default: every tool in the manifest)
+        tools: Vec<String>,
+
         /// Path to the manifest file
         #[arg(short, long, default_value = "nursery.toml")]
         manifest: PathBuf,
+
+        /// Apply a `[profile.<name>]` overlay from the manifest
+        #[arg(long, env = "MYENV_PROFILE")]
+        profile: Option<String>,
+
+        /// Overwrite configs that were edited since they were last generated
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Apply changes in both directions, using the last generation as base
+    Sync {
+        /// Path to the manifest file
+        #[arg(short, long, default_value = "nursery.toml")]
+        manifest: PathBuf,
+
+        /// Apply a `[profile.<name>]` overlay from the manifest
+        #[arg(long, env = "MYENV_PROFILE")]
+        profile: Option<String>,
+
+        /// Report changes and conflicts without writing
+        #[arg(long)]
+        dry_run: bool,
     },
 
     /// Pull tool config files into nursery.toml
@@ -143,6 +265,10 @@ enum ConfigAction {
         #[arg(value_name = "TOOL")]
         tools: Vec<String>,
 
+        /// Apply a `[profile.<name>]` overlay from the manifest
+        #[arg(long, env = "MYENV_PROFILE")]
+        profile: Option<String>,
+
         /// Don't write, just show what would be pulled
         #[arg(long)]
         dry_run: bool,
@@ -158,27 +284,107 @@ fn parse_var(s: &str) -> Result<(String, String), String> {
 
 fn main() -> ExitCode {
     let cli = Cli::parse();
+    let output = cli.output;
+    let schema = commands::SchemaOptions {
+        timeout: cli.schema_timeout,
+        cache: !cli.no_schema_cache,
+    };
 
     match cli.command {
         Command::Generate {
+            tools,
+            exclude,
             manifest,
+            profile,
             check,
             diff,
+            diff_format,
+            color,
             watch,
+            allow_undefined,
+            force,
+            keep_going,
         } => {
+            let options = GenerateOptions {
+                allow_undefined,
+                force,
+                tools,
+                exclude,
+                keep_going,
+            };
             if watch {
-                commands::generate::watch(&manifest)
+                commands::generate::watch(&manifest, profile.as_deref(), &options, output, &schema)
             } else {
-                commands::generate::run(&manifest, check, diff)
+                let diff_format = match output {
+                    OutputFormat::Json => commands::generate::DiffFormat::Json,
+                    OutputFormat::Text => diff_format,
+                };
+                let diff = diff.then(|| commands::generate::DiffOutput::new(diff_format, color));
+                commands::generate::run(
+                    &manifest,
+                    profile.as_deref(),
+                    &options,
+                    check,
+                    diff.as_ref(),
+                    output,
+                    &schema,
+                )
             }
         }
+        Command::Clean {
+            manifest,
+            profile,
+            dry_run,
+            stale,
+            force,
+        } => commands::clean::run(
+            &manifest,
+            profile.as_deref(),
+            &CleanOptions {
+                dry_run,
+                stale_only: stale,
+                force,
+            },
+            output,
+            &schema,
+        ),
         Command::Config { action } => match action {
-            ConfigAction::Push { manifest } => commands::generate::run(&manifest, false, false),
+            ConfigAction::Push {
+                tools,
+                manifest,
+                profile,
+                force,
+            } => commands::generate::run(
+                &manifest,
+                profile.as_deref(),
+                &GenerateOptions {
+                    force,
+                    tools,
+                    ..Default::default()
+                },
+                false,
+                None,
+                output,
+                &schema,
+            ),
+            ConfigAction::Sync {
+                manifest,
+                profile,
+                dry_run,
+            } => commands::config::sync(&manifest, profile.as_deref(), dry_run, output, &schema),
             ConfigAction::Pull {
                 manifest,
                 tools,
+                profile,
+                dry_run,
+            } => commands::config::pull(
+                &manifest,
+                tools,
+                profile.as_deref(),
                 dry_run,
-            } => commands::config::pull(&manifest, tools, dry_run),
+                output,
+                &schema,
+            ),
         },
         Command::Init {
             name,
@@ -186,23 +392,28 @@ fn main() -> ExitCode {
             vars,
             raw,
             no_prompt,
-        } => commands::init::run(&name, &seed, vars, raw, no_prompt),
-        Command::Seeds => commands::seeds::run(),
+        } => commands::init::run(&name, &seed, vars, raw, no_prompt, output),
+        Command::Seeds => commands::seeds::run(output),
+        Command::Schema {
+            action: SchemaAction::Cache {
+                action: CacheAction::Clear,
+            },
+        } => commands::schema::clear_cache(output),
         Command::Tools { action } => match action {
             ToolsAction::Check {
                 manifest,
                 dev,
                 build,
-            } => commands::tools::check(&manifest, dev, build),
+            } => commands::tools::check(&manifest, dev, build, output),
             ToolsAction::Install {
                 manifest,
                 dry_run,
                 dev,
                 build,
-            } => commands::tools::install(&manifest, dry_run, dev, build),
-            ToolsAction::Ecosystems => commands::tools::ecosystems(),
-            ToolsAction::Lookup { tool } => commands::tools::lookup(&tool),
-            ToolsAction::Lock { manifest } => commands::tools::lock(&manifest),
+            } => commands::tools::install(&manifest, dry_run, dev, build, output),
+            ToolsAction::Ecosystems => commands::tools::ecosystems(output),
+            ToolsAction::Lookup { tool } => commands::tools::lookup(&tool, output),
+            ToolsAction::Lock { manifest } => commands::tools::lock(&manifest, output),
         },
     }
 }
@@ -0,0 +1,28 @@
+//! Output format shared by all commands.
+
+use serde::Serialize;
+
+/// How commands report their results on stdout.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// Human-readable text.
+    #[default]
+    Text,
+    /// A single JSON document per command. Errors, warnings and hints are
+    /// still written to stderr as text.
+    Json,
+}
+
+impl OutputFormat {
+    pub fn is_json(self) -> bool {
+        self == Self::Json
+    }
+}
+
+/// Print a result as pretty JSON on stdout.
+pub fn print_json(value: &impl Serialize) {
+    match serde_json::to_string_pretty(value) {
+        Ok(json) => println!("{json}"),
+        Err(e) => eprintln!("error: failed to serialize output: {e}"),
+    }
+}
@@ -0,0 +1,199 @@
+//! On-disk cache of tool schemas.
+//!
+//! Running `<tool> --schema` on every `generate`, `--diff` and watch
+//! iteration adds up. Cached schemas are stored per tool alongside the
+//! provider's [`cache_key`](SchemaProvider::cache_key) (for CLI tools, the
+//! binary's path, size and mtime), and refetched once the key changes.
+
+use crate::schema::{SchemaError, SchemaProvider, ToolSchema};
+use crate::state::write_atomic;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Wraps a provider, caching the schemas it returns on disk.
+///
+/// Failed fetches are never cached, and cache read or write failures fall
+/// back to the wrapped provider.
+#[derive(Debug, Clone)]
+pub struct CachingSchemaProvider<P> {
+    inner: P,
+    cache_dir: PathBuf,
+}
+
+/// A cache file: the schema and the key it was fetched under.
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedSchema {
+    key: String,
+    schema: ToolSchema,
+}
+
+impl<P: SchemaProvider> CachingSchemaProvider<P> {
+    /// Cache schemas in the user cache directory.
+    pub fn new(inner: P) -> Self {
+        let cache_dir = dirs::cache_dir()
+            .unwrap_or_else(|| PathBuf::from(".cache"))
+            .join("myenv")
+            .join("schemas");
+        Self::with_cache_dir(inner, cache_dir)
+    }
+
+    /// Cache schemas in a custom directory.
+    pub fn with_cache_dir(inner: P, cache_dir: PathBuf) -> Self {
+        Self { inner, cache_dir }
+    }
+
+    /// Directory holding the cached schemas.
+    pub fn cache_dir(&self) -> &Path {
+        &self.cache_dir
+    }
+
+    /// Remove every cached schema.
+    pub fn clear_cache(&self) -> io::Result<()> {
+        match fs::remove_dir_all(&self.cache_dir) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    fn cache_path(&self, tool: &str) -> PathBuf {
+        self.cache_dir.join(format!("{tool}.json"))
+    }
+
+    /// A cached schema, if one was stored under the tool's current key.
+    fn read_cache(&self, tool: &str, key: &str) -> Option<ToolSchema> {
+        let contents = fs::read_to_string(self.cache_path(tool)).ok()?;
+        let cached: CachedSchema = serde_json::from_str(&contents).ok()?;
+        (cached.key == key).then_some(cached.schema)
+    }
+
+    fn write_cache(&self, tool: &str, key: String, schema: &ToolSchema) {
+        let cached = CachedSchema {
+            key,
+            schema: schema.clone(),
+        };
+        if let Ok(contents) = serde_json::to_string_pretty(&cached) {
+            let _ = write_atomic(&self.cache_path(tool), contents.as_bytes());
+        }
+    }
+}
+
+impl<P: SchemaProvider> SchemaProvider for CachingSchemaProvider<P> {
+    fn fetch(&self, tool: &str) -> Result<ToolSchema, SchemaError> {
+        self.fetch_all(&[tool]).remove(0)
+    }
+
+    fn fetch_all(&self, tools: &[&str]) -> Vec<Result<ToolSchema, SchemaError>> {
+        let keys: Vec<_> = tools
+            .iter()
+            .map(|tool| self.inner.cache_key(tool))
+            .collect();
+        let mut results: Vec<_> = tools
+            .iter()
+            .zip(&keys)
+            .map(|(tool, key)| {
+                let key = key.as_deref()?;
+                self.read_cache(tool, key).map(Ok)
+            })
+            .collect();
+
+        // Fetch every miss in one go, so the inner provider can parallelize
+        let misses: Vec<_> = tools
+            .iter()
+            .zip(&results)
+            .filter(|(_, result)| result.is_none())
+            .map(|(tool, _)| *tool)
+            .collect();
+        let mut fetched = self.inner.fetch_all(&misses).into_iter();
+
+        for ((tool, key), result) in tools.iter().zip(keys).zip(&mut results) {
+            if result.is_some() {
+                continue;
+            }
+            let Some(schema) = fetched.next() else {
+                break;
+            };
+            if let (Ok(schema), Some(key)) = (&schema, key) {
+                self.write_cache(tool, key, schema);
+            }
+            *result = Some(schema);
+        }
+        results.into_iter().flatten().collect()
+    }
+
+    fn cache_key(&self, tool: &str) -> Option<String> {
+        self.inner.cache_key(tool)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::ConfigFormat;
+    use std::cell::{Cell, RefCell};
+
+    /// Counts fetches; `cache_key` returns the current `version`.
+    struct CountingProvider {
+        fetches: Cell<usize>,
+        version: RefCell<Option<String>>,
+    }
+
+    impl SchemaProvider for CountingProvider {
+        fn fetch(&self, tool: &str) -> Result<ToolSchema, SchemaError> {
+            self.fetches.set(self.fetches.get() + 1);
+            if tool == "missing" {
+                return Err(SchemaError::ToolNotFound(tool.to_string()));
+            }
+            Ok(ToolSchema {
+                config_path: format!(".{tool}/config.toml").into(),
+                format: ConfigFormat::Toml,
+                schema: serde_json::json!({}),
+            })
+        }
+
+        fn cache_key(&self, _tool: &str) -> Option<String> {
+            self.version.borrow().clone()
+        }
+    }
+
+    #[test]
+    fn caches_until_key_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let inner = CountingProvider {
+            fetches: Cell::new(0),
+            version: RefCell::new(Some("v1".into())),
+        };
+        let provider = CachingSchemaProvider::with_cache_dir(inner, dir.path().join("schemas"));
+
+        let results = provider.fetch_all(&["dew", "missing"]);
+        assert_eq!(
+            results[0].as_ref().unwrap().config_path,
+            Path::new(".dew/config.toml")
+        );
+        assert!(results[1].is_err());
+        assert_eq!(provider.inner.fetches.get(), 2);
+
+        // Only the failure is fetched again
+        let results = provider.fetch_all(&["dew", "missing"]);
+        assert!(results[0].is_ok() && results[1].is_err());
+        assert_eq!(provider.inner.fetches.get(), 3);
+
+        // A new binary invalidates the entry
+        *provider.inner.version.borrow_mut() = Some("v2".into());
+        provider.fetch("dew").unwrap();
+        assert_eq!(provider.inner.fetches.get(), 4);
+        provider.fetch("dew").unwrap();
+        assert_eq!(provider.inner.fetches.get(), 4);
+
+        // Without a key nothing is cached
+        *provider.inner.version.borrow_mut() = None;
+        provider.fetch("dew").unwrap();
+        provider.fetch("dew").unwrap();
+        assert_eq!(provider.inner.fetches.get(), 6);
+
+        provider.clear_cache().unwrap();
+        assert!(!provider.cache_dir().exists());
+        provider.clear_cache().unwrap();
+    }
+}
@@ -0,0 +1,219 @@
+//! Removing generated configs.
+//!
+//! Only files recorded in `.myenv/generated.toml` are ever removed, so
+//! hand-written configs are never touched. Generated files that were edited
+//! since they were written are kept unless forced.
+
+use crate::generate::{GenerateError, fetch_schemas};
+use crate::manifest::Manifest;
+use crate::schema::SchemaProvider;
+use crate::state::{GeneratedFile, GenerationState, hash_contents};
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Options controlling `clean_configs`.
+#[derive(Debug, Clone, Default)]
+pub struct CleanOptions {
+    /// Report what would be removed without removing anything.
+    pub dry_run: bool,
+    /// Only remove configs for tools no longer in the manifest, or whose
+    /// config path changed.
+    pub stale_only: bool,
+    /// Also remove configs that were edited since they were generated.
+    pub force: bool,
+}
+
+/// Outcome for a single generated config.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "status", rename_all = "lowercase")]
+pub enum CleanResult {
+    /// Config was removed (or would be, in a dry run).
+    Removed { tool: String, path: PathBuf },
+    /// Config was kept because it was edited since it was generated.
+    Edited { tool: String, path: PathBuf },
+}
+
+/// Remove configs previously written by `generate_configs`.
+pub fn clean_configs(
+    manifest: &Manifest,
+    provider: &dyn SchemaProvider,
+    base_dir: &Path,
+    options: &CleanOptions,
+) -> Result<Vec<CleanResult>, GenerateError> {
+    let mut state = GenerationState::load(base_dir)?;
+    let mut results = Vec::new();
+
+    for (tool, file) in state.tools.clone() {
+        if options.stale_only && !is_stale(manifest, provider, &tool, &file) {
+            continue;
+        }
+        let result = remove_generated(&tool, &file, base_dir, options.dry_run, options.force)?;
+        if !options.dry_run && !matches!(result, Some(CleanResult::Edited { .. })) {
+            state.forget(&tool, base_dir);
+        }
+        results.extend(result);
+    }
+
+    if !options.dry_run {
+        state.save(base_dir)?;
+    }
+    Ok(results)
+}
+
+/// Whether a recorded config no longer matches the manifest.
+///
+/// Tools whose schema can't be fetched keep their config, since their
+/// current path is unknown.
+fn is_stale(
+    manifest: &Manifest,
+    provider: &dyn SchemaProvider,
+    tool: &str,
+    file: &GeneratedFile,
+) -> bool {
+    if !manifest.has_target(tool) {
+        return true;
+    }
+    fetch_schemas(provider, Some(manifest), &[tool.to_string()])
+        .remove(0)
+        .is_ok_and(|schema| schema.config_path != file.path)
+}
+
+/// Remove a generated file unless it was edited since it was written.
+///
+/// Returns `None` if the file is already gone. Empty parent directories
+/// (e.g. `.dew/`) are removed along with it.
+pub(crate) fn remove_generated(
+    tool: &str,
+    file: &GeneratedFile,
+    base_dir: &Path,
+    dry_run: bool,
+    force: bool,
+) -> Result<Option<CleanResult>, GenerateError> {
+    let path = base_dir.join(&file.path);
+    let Ok(contents) = fs::read(&path) else {
+        return Ok(None);
+    };
+
+    if !force && hash_contents(&contents) != file.hash {
+        return Ok(Some(CleanResult::Edited {
+            tool: tool.to_string(),
+            path,
+        }));
+    }
+
+    if !dry_run {
+        fs::remove_file(&path).map_err(|e| GenerateError::RemoveConfig(tool.to_string(), e))?;
+        if let Some(parent) = path.parent().filter(|p| *p != base_dir) {
+            // Fails harmlessly if the directory holds anything else
+            let _ = fs::remove_dir(parent);
+        }
+    }
+
+    Ok(Some(CleanResult::Removed {
+        tool: tool.to_string(),
+        path,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::{ConfigFormat, SchemaError, ToolSchema};
+
+    /// Every tool writes `.<tool>/config.toml`.
+    struct DotDirProvider;
+
+    impl SchemaProvider for DotDirProvider {
+        fn fetch(&self, tool: &str) -> Result<ToolSchema, SchemaError> {
+            Ok(ToolSchema {
+                config_path: format!(".{tool}/config.toml").into(),
+                format: ConfigFormat::Toml,
+                schema: serde_json::json!({"type": "object"}),
+            })
+        }
+    }
+
+    fn record(state: &mut GenerationState, dir: &Path, tool: &str, rel: &str, contents: &str) {
+        let path = dir.join(rel);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, contents).unwrap();
+        state.record(tool, dir, &path, contents.as_bytes());
+    }
+
+    #[test]
+    fn clean_only_touches_recorded_unedited_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let manifest = Manifest::parse(
+            r#"
+            [project]
+            name = "test"
+
+            [dew]
+            output = "./out"
+
+            [lotus]
+            port = 8080
+        "#,
+            Path::new("."),
+        )
+        .unwrap();
+
+        let mut state = GenerationState::default();
+        record(&mut state, dir.path(), "dew", ".dew/config.toml", "a = 1\n");
+        record(&mut state, dir.path(), "lotus", "lotus.toml", "b = 2\n");
+        record(
+            &mut state,
+            dir.path(),
+            "siphon",
+            ".siphon/config.toml",
+            "c = 3\n",
+        );
+        record(&mut state, dir.path(), "old", ".old/config.toml", "d = 4\n");
+        state.save(dir.path()).unwrap();
+        fs::write(dir.path().join(".old/config.toml"), "d = 5\n").unwrap();
+        fs::write(dir.path().join(".siphon/notes.txt"), "mine").unwrap();
+
+        // Stale: siphon and old are gone from the manifest, lotus moved
+        let stale = CleanOptions {
+            dry_run: true,
+            stale_only: true,
+            ..Default::default()
+        };
+        let results = clean_configs(&manifest, &DotDirProvider, dir.path(), &stale).unwrap();
+        let summary: Vec<_> = results
+            .iter()
+            .map(|r| match r {
+                CleanResult::Removed { tool, .. } => format!("removed {tool}"),
+                CleanResult::Edited { tool, .. } => format!("edited {tool}"),
+            })
+            .collect();
+        assert_eq!(summary, ["removed lotus", "edited old", "removed siphon"]);
+        assert!(dir.path().join("lotus.toml").exists());
+
+        let stale = CleanOptions {
+            stale_only: true,
+            ..Default::default()
+        };
+        clean_configs(&manifest, &DotDirProvider, dir.path(), &stale).unwrap();
+        assert!(!dir.path().join("lotus.toml").exists());
+        assert!(!dir.path().join(".siphon/config.toml").exists());
+        assert!(dir.path().join(".siphon/notes.txt").exists());
+        assert!(dir.path().join(".old/config.toml").exists());
+        assert!(dir.path().join(".dew/config.toml").exists());
+
+        let state = GenerationState::load(dir.path()).unwrap();
+        let tools: Vec<_> = state.tools.keys().collect();
+        assert_eq!(tools, ["dew", "old"]);
+
+        // A full clean removes everything left, forcing past edits
+        let all = CleanOptions {
+            force: true,
+            ..Default::default()
+        };
+        clean_configs(&manifest, &DotDirProvider, dir.path(), &all).unwrap();
+        assert!(!dir.path().join(".dew").exists());
+        assert!(!dir.path().join(".old").exists());
+        assert!(GenerationState::load(dir.path()).unwrap().tools.is_empty());
+    }
+}
@@ -0,0 +1,290 @@
+//! Line-based unified diffs for previewing config changes.
+//!
+//! Uses Myers' O(ND) algorithm, so reordered and duplicate lines are shown
+//! as the minimal set of removals and additions. Lines are compared with their
+//! line endings, so CRLF/LF changes and a missing final newline show up as
+//! changed lines.
+
+use serde::Serialize;
+use std::fmt;
+
+/// Default number of unchanged lines shown around each change.
+pub const DEFAULT_CONTEXT: usize = 3;
+
+/// A line in a hunk.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", content = "text", rename_all = "lowercase")]
+pub enum DiffLine {
+    /// Unchanged line shown for context.
+    Context(String),
+    /// Line only in the old text.
+    Removed(String),
+    /// Line only in the new text.
+    Added(String),
+    /// The previous line has no line ending (`\ No newline at end of file`).
+    #[serde(rename = "no_newline")]
+    NoNewline,
+}
+
+/// A group of nearby changes with surrounding context.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Hunk {
+    /// First old line in the hunk (1-based; the preceding line if empty).
+    pub old_start: usize,
+    /// Number of old lines in the hunk.
+    pub old_len: usize,
+    /// First new line in the hunk (1-based; the preceding line if empty).
+    pub new_start: usize,
+    /// Number of new lines in the hunk.
+    pub new_len: usize,
+    /// Lines in order.
+    pub lines: Vec<DiffLine>,
+}
+
+impl Hunk {
+    /// The `@@ -a,b +c,d @@` header.
+    pub fn header(&self) -> String {
+        format!(
+            "@@ -{},{} +{},{} @@",
+            self.old_start, self.old_len, self.new_start, self.new_len
+        )
+    }
+}
+
+impl fmt::Display for DiffLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DiffLine::Context(line) => write!(f, " {line}"),
+            DiffLine::Removed(line) => write!(f, "-{line}"),
+            DiffLine::Added(line) => write!(f, "+{line}"),
+            DiffLine::NoNewline => write!(f, "\\ No newline at end of file"),
+        }
+    }
+}
+
+impl fmt::Display for Hunk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", self.header())?;
+        for line in &self.lines {
+            writeln!(f, "{line}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A step in the edit script.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    /// Old line `.0` equals new line `.1`.
+    Equal(usize, usize),
+    /// Old line removed.
+    Delete(usize),
+    /// New line added.
+    Insert(usize),
+}
+
+/// Diff two texts line by line, grouping changes into hunks with `context`
+/// unchanged lines around them. Identical texts produce no hunks.
+pub fn diff_lines(old: &str, new: &str, context: usize) -> Vec<Hunk> {
+    let a: Vec<&str> = old.split_inclusive('\n').collect();
+    let b: Vec<&str> = new.split_inclusive('\n').collect();
+    let ops = edit_script(&a, &b);
+
+    let changes: Vec<usize> = ops
+        .iter()
+        .enumerate()
+        .filter(|(_, op)| !matches!(op, Op::Equal(..)))
+        .map(|(i, _)| i)
+        .collect();
+
+    let mut hunks = Vec::new();
+    let mut i = 0;
+    while i < changes.len() {
+        let start = changes[i].saturating_sub(context);
+        let mut end = changes[i] + 1;
+        i += 1;
+        // Merge changes whose context would overlap
+        while i < changes.len() && changes[i] - end < 2 * context + 1 {
+            end = changes[i] + 1;
+            i += 1;
+        }
+        let end = (end + context).min(ops.len());
+
+        // Lines of each side consumed before the hunk
+        let old_before = ops[..start]
+            .iter()
+            .filter(|op| !matches!(op, Op::Insert(_)))
+            .count();
+        let new_before = ops[..start]
+            .iter()
+            .filter(|op| !matches!(op, Op::Delete(_)))
+            .count();
+        hunks.push(build_hunk(&ops[start..end], &a, &b, old_before, new_before));
+    }
+    hunks
+}
+
+fn build_hunk(ops: &[Op], a: &[&str], b: &[&str], old_before: usize, new_before: usize) -> Hunk {
+    let mut lines = Vec::new();
+    let (mut old_len, mut new_len) = (0, 0);
+    for op in ops {
+        let line = match *op {
+            Op::Equal(x, _) => {
+                old_len += 1;
+                new_len += 1;
+                a[x]
+            }
+            Op::Delete(x) => {
+                old_len += 1;
+                a[x]
+            }
+            Op::Insert(y) => {
+                new_len += 1;
+                b[y]
+            }
+        };
+        let text = line.strip_suffix('\n').unwrap_or(line).to_string();
+        lines.push(match op {
+            Op::Equal(..) => DiffLine::Context(text),
+            Op::Delete(_) => DiffLine::Removed(text),
+            Op::Insert(_) => DiffLine::Added(text),
+        });
+        if !line.ends_with('\n') {
+            lines.push(DiffLine::NoNewline);
+        }
+    }
+
+    let start = |before: usize, len: usize| if len == 0 { before } else { before + 1 };
+    Hunk {
+        old_start: start(old_before, old_len),
+        old_len,
+        new_start: start(new_before, new_len),
+        new_len,
+        lines,
+    }
+}
+
+/// Shortest edit script between two line sequences (Myers' algorithm).
+fn edit_script(a: &[&str], b: &[&str]) -> Vec<Op> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max = n + m;
+    let offset = max as usize;
+    let mut v = vec![0isize; 2 * offset + 2];
+    let mut trace = Vec::new();
+
+    'search: for d in 0..=max {
+        trace.push(v.clone());
+        for k in (-d..=d).step_by(2) {
+            let idx = (k + max) as usize;
+            let mut x = if k == -d || (k != d && v[idx - 1] < v[idx + 1]) {
+                v[idx + 1]
+            } else {
+                v[idx - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[idx] = x;
+            if x >= n && y >= m {
+                break 'search;
+            }
+        }
+    }
+
+    // Walk back through the trace to recover the path
+    let mut ops = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let k = x - y;
+        let idx = (k + max) as usize;
+        let prev_k = if k == -d || (k != d && v[idx - 1] < v[idx + 1]) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = v[(prev_k + max) as usize];
+        let prev_y = prev_x - prev_k;
+
+        while x > prev_x && y > prev_y {
+            x -= 1;
+            y -= 1;
+            ops.push(Op::Equal(x as usize, y as usize));
+        }
+        if d > 0 {
+            if x == prev_x {
+                ops.push(Op::Insert((y - 1) as usize));
+            } else {
+                ops.push(Op::Delete((x - 1) as usize));
+            }
+        }
+        x = prev_x;
+        y = prev_y;
+    }
+    ops.reverse();
+    ops
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(hunks: &[Hunk]) -> String {
+        hunks.iter().map(|h| h.to_string()).collect()
+    }
+
+    #[test]
+    fn identical_texts_have_no_hunks() {
+        assert!(diff_lines("a\nb\n", "a\nb\n", 3).is_empty());
+        assert!(diff_lines("", "", 3).is_empty());
+    }
+
+    #[test]
+    fn changes_have_context() {
+        let old = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\n";
+        let new = "a\nb\nc\nd\nE\nf\ng\nh\ni\nj\nk\n";
+        assert_eq!(
+            render(&diff_lines(old, new, 2)),
+            "@@ -3,5 +3,5 @@\n c\n d\n-e\n+E\n f\n g\n@@ -9,2 +9,3 @@\n i\n j\n+k\n"
+        );
+        // With more context the two changes share a hunk
+        assert_eq!(diff_lines(old, new, 3).len(), 1);
+    }
+
+    #[test]
+    fn reordered_and_duplicate_lines() {
+        // The old naive diff showed nothing here
+        let hunks = diff_lines("x\ny\nx\n", "x\nx\ny\n", 0);
+        let lines: Vec<_> = hunks.iter().flat_map(|h| &h.lines).collect();
+        assert_eq!(
+            lines,
+            [&DiffLine::Removed("y".into()), &DiffLine::Added("y".into())]
+        );
+
+        let hunks = diff_lines("", "new\n", 3);
+        assert_eq!(render(&hunks), "@@ -0,0 +1,1 @@\n+new\n");
+    }
+
+    #[test]
+    fn line_ending_changes_are_shown() {
+        let hunks = diff_lines("a\nb", "a\nb\n", 3);
+        assert_eq!(
+            render(&hunks),
+            "@@ -1,2 +1,2 @@\n a\n-b\n\\ No newline at end of file\n+b\n"
+        );
+
+        let hunks = diff_lines("a\r\nb\r\n", "a\nb\n", 0);
+        let lines: Vec<_> = hunks.iter().flat_map(|h| &h.lines).collect();
+        assert_eq!(
+            lines,
+            [
+                &DiffLine::Removed("a\r".into()),
+                &DiffLine::Removed("b\r".into()),
+                &DiffLine::Added("a".into()),
+                &DiffLine::Added("b".into()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,720 @@
+//! Placeholder expansion for tool configs.
+//!
+//! Placeholders can name a variable (`{{assets}}`) or a value in another
+//! tool section (`{{siphon.output}}`). Referenced values are expanded on
+//! demand, so variables may build on each other, and reference cycles are
+//! reported rather than producing half-expanded output.
+//!
+//! `{{env.NAME}}` reads an environment variable and `{{cmd: git rev-parse
+//! HEAD}}` runs a command; both only work for names allow-listed in the
+//! manifest's `[myenv]` section.
+
+use crate::generate::toml_to_json;
+use crate::manifest::{Manifest, Settings, TOOL_SETTINGS_KEY};
+use myenv_template::{Template, TemplateError};
+use std::collections::HashMap;
+use std::process::Command;
+
+/// Reference prefix for environment variables.
+const ENV_PREFIX: &str = "env.";
+
+/// Reference prefix for command output.
+const CMD_PREFIX: &str = "cmd:";
+
+/// One step in the path to a value inside a config.
+#[derive(Debug, Clone)]
+pub(crate) enum PathSegment {
+    Key(String),
+    Index(usize),
+}
+
+/// A template problem at a specific location in a config.
+#[derive(Debug)]
+pub(crate) struct TemplateIssue {
+    /// Location of the offending string.
+    pub path: Vec<PathSegment>,
+    /// What went wrong.
+    pub error: TemplateError,
+}
+
+impl TemplateIssue {
+    /// Dotted key path including the tool name, e.g. `dew.inputs[0]`.
+    pub fn key_path(&self, tool_name: &str) -> String {
+        let mut out = tool_name.to_string();
+        for segment in &self.path {
+            match segment {
+                PathSegment::Key(k) => {
+                    out.push('.');
+                    out.push_str(k);
+                }
+                PathSegment::Index(i) => out.push_str(&format!("[{i}]")),
+            }
+        }
+        out
+    }
+
+    /// JSON pointer to the offending value, e.g. `/inputs/0`.
+    pub fn pointer(&self) -> String {
+        self.path
+            .iter()
+            .map(|segment| match segment {
+                PathSegment::Key(k) => format!("/{k}"),
+                PathSegment::Index(i) => format!("/{i}"),
+            })
+            .collect()
+    }
+}
+
+/// Resolves placeholders against manifest variables and tool sections.
+///
+/// A string consisting of exactly one placeholder is replaced by the
+/// referenced value with its type intact (integer, bool, array, table).
+/// Placeholders inside longer strings are interpolated as text; only scalar
+/// values can be interpolated this way.
+pub(crate) struct Resolver {
+    /// Raw variable values, including project `name` and `version`.
+    variables: HashMap<String, serde_json::Value>,
+    /// Raw tool sections, keyed by tool name, plus merged instance
+    /// sections keyed by `<tool>.<instance>`.
+    tools: HashMap<String, serde_json::Value>,
+    /// Allow-lists for environment variables and commands.
+    settings: Settings,
+    /// Fully expanded values of references resolved so far.
+    resolved: HashMap<String, serde_json::Value>,
+    /// References currently being resolved, for cycle detection.
+    stack: Vec<String>,
+    /// Report undefined and malformed placeholders instead of leaving them.
+    strict: bool,
+    /// Location of the string currently being expanded.
+    path: Vec<PathSegment>,
+    /// Problems found in the value currently being expanded.
+    issues: Vec<TemplateIssue>,
+}
+
+impl Resolver {
+    /// Create a resolver for a manifest's variables and tool sections.
+    pub fn new(manifest: &Manifest, strict: bool) -> Self {
+        let mut variables: HashMap<String, serde_json::Value> = manifest
+            .variables
+            .iter()
+            .map(|(k, v)| (k.clone(), toml_to_json(v)))
+            .collect();
+        variables.insert(
+            "name".to_string(),
+            serde_json::Value::String(manifest.project.name.clone()),
+        );
+        if let Some(version) = &manifest.project.version {
+            variables.insert(
+                "version".to_string(),
+                serde_json::Value::String(version.clone()),
+            );
+        }
+
+        let mut tools: HashMap<_, _> = manifest
+            .tool_configs
+            .iter()
+            .map(|(k, v)| (k.clone(), toml_to_json(v)))
+            .collect();
+        // Instances are expanded and referenced by their own identifier
+        for target in manifest.targets() {
+            if target.instance.is_some()
+                && let Some(config) = manifest.target_config(&target.id)
+            {
+                tools.insert(target.id, toml_to_json(&config));
+            }
+        }
+
+        let mut resolver = Self::from_parts(variables, tools, strict);
+        resolver.settings = manifest.settings.clone();
+        resolver
+    }
+
+    /// Create a resolver from raw variables and tool sections.
+    pub fn from_parts(
+        variables: HashMap<String, serde_json::Value>,
+        tools: HashMap<String, serde_json::Value>,
+        strict: bool,
+    ) -> Self {
+        Self {
+            variables,
+            tools,
+            settings: Settings::default(),
+            resolved: HashMap::new(),
+            stack: Vec::new(),
+            strict,
+            path: Vec::new(),
+            issues: Vec::new(),
+        }
+    }
+
+    /// Expand a tool's section, returning the result and any problems found.
+    ///
+    /// The reserved `_myenv` sub-table is not part of the tool's config and
+    /// is left out.
+    pub fn expand_tool(&mut self, tool_name: &str) -> (serde_json::Value, Vec<TemplateIssue>) {
+        let mut config = self
+            .tools
+            .get(tool_name)
+            .cloned()
+            .unwrap_or(serde_json::Value::Object(serde_json::Map::new()));
+        if let Some(obj) = config.as_object_mut() {
+            obj.remove(TOOL_SETTINGS_KEY);
+        }
+        self.expand_root(&config, Some(tool_name))
+    }
+
+    /// Expand an arbitrary value, returning the result and any problems found.
+    #[cfg(test)]
+    pub fn expand_value(
+        &mut self,
+        value: &serde_json::Value,
+    ) -> (serde_json::Value, Vec<TemplateIssue>) {
+        self.expand_root(value, None)
+    }
+
+    /// Render a standalone template, such as a config path, to text.
+    ///
+    /// `extra` values take precedence over manifest variables. Undefined
+    /// placeholders are always an error, regardless of strictness.
+    pub fn render(
+        &mut self,
+        template: &str,
+        extra: &HashMap<&str, String>,
+    ) -> Result<String, TemplateError> {
+        let template = Template::parse(template)?;
+        let mut values = HashMap::new();
+        for p in template.placeholders() {
+            if let Some(value) = extra.get(p.name.as_str()) {
+                values.insert(p.name.clone(), value.clone());
+            } else if let Some(value) = self.lookup(&p.name)? {
+                let text = scalar_to_string(&value)
+                    .ok_or_else(|| TemplateError::NotInterpolable(p.name.clone()))?;
+                values.insert(p.name.clone(), text);
+            }
+        }
+        template.render(|name| values.get(name).cloned())
+    }
+
+    /// Fully expanded value of a reference, if it resolves without problems.
+    pub fn resolve(&mut self, name: &str) -> Option<serde_json::Value> {
+        self.lookup(name).ok().flatten()
+    }
+
+    fn expand_root(
+        &mut self,
+        value: &serde_json::Value,
+        tool_name: Option<&str>,
+    ) -> (serde_json::Value, Vec<TemplateIssue>) {
+        self.path.clear();
+        self.issues.clear();
+        let expanded = self.expand(value, tool_name);
+        (expanded, std::mem::take(&mut self.issues))
+    }
+
+    fn expand(&mut self, value: &serde_json::Value, tool_name: Option<&str>) -> serde_json::Value {
+        match value {
+            serde_json::Value::String(s) => match tool_name {
+                // Put this key on the stack so cycles through it are caught
+                Some(tool) => {
+                    let key = dotted(tool, &self.path);
+                    self.stack.push(key);
+                    let expanded = self.expand_string(s);
+                    self.stack.pop();
+                    expanded
+                }
+                None => self.expand_string(s),
+            },
+            serde_json::Value::Array(arr) => {
+                let mut out = Vec::with_capacity(arr.len());
+                for (i, v) in arr.iter().enumerate() {
+                    self.path.push(PathSegment::Index(i));
+                    out.push(self.expand(v, tool_name));
+                    self.path.pop();
+                }
+                serde_json::Value::Array(out)
+            }
+            serde_json::Value::Object(obj) => {
+                let mut map = serde_json::Map::new();
+                for (k, v) in obj {
+                    self.path.push(PathSegment::Key(k.clone()));
+                    map.insert(k.clone(), self.expand(v, tool_name));
+                    self.path.pop();
+                }
+                serde_json::Value::Object(map)
+            }
+            other => other.clone(),
+        }
+    }
+
+    fn expand_string(&mut self, s: &str) -> serde_json::Value {
+        let template = match Template::parse(s) {
+            Ok(t) => t,
+            Err(e) => {
+                self.report(e);
+                return serde_json::Value::String(s.to_string());
+            }
+        };
+
+        // Whole-value placeholders keep the referenced value's type, unless
+        // a filter turns it into a string
+        if let Some(p) = template.as_placeholder().filter(|p| p.is_passthrough()) {
+            match self.lookup(&p.name) {
+                Ok(Some(value)) => return value,
+                Ok(None) => {}
+                Err(e) => {
+                    self.report(e);
+                    return serde_json::Value::String(s.to_string());
+                }
+            }
+        }
+
+        let mut values = HashMap::new();
+        for p in template.placeholders() {
+            match self.lookup(&p.name) {
+                Ok(Some(value)) => match scalar_to_string(&value) {
+                    Some(text) => {
+                        values.insert(p.name.clone(), text);
+                    }
+                    None => {
+                        self.report(TemplateError::NotInterpolable(p.name.clone()));
+                        return serde_json::Value::String(s.to_string());
+                    }
+                },
+                Ok(None) => {}
+                Err(e) => {
+                    self.report(e);
+                    return serde_json::Value::String(s.to_string());
+                }
+            }
+        }
+
+        let lookup = |name: &str| values.get(name).cloned();
+        if !self.strict {
+            return serde_json::Value::String(template.render_lenient(lookup));
+        }
+        match template.render(lookup) {
+            Ok(rendered) => serde_json::Value::String(rendered),
+            Err(e) => {
+                self.report(e);
+                serde_json::Value::String(s.to_string())
+            }
+        }
+    }
+
+    /// Resolve a reference to its fully expanded value.
+    ///
+    /// Returns `Ok(None)` if nothing by that name exists. Problems inside the
+    /// referenced value are returned as the error for this reference.
+    fn lookup(&mut self, name: &str) -> Result<Option<serde_json::Value>, TemplateError> {
+        if let Some(value) = self.resolved.get(name) {
+            return Ok(Some(value.clone()));
+        }
+        if let Some(var) = name.strip_prefix(ENV_PREFIX) {
+            return self.env_var(var);
+        }
+        if let Some(command) = name.strip_prefix(CMD_PREFIX) {
+            let output = self.run_command(command.trim())?;
+            self.resolved.insert(name.to_string(), output.clone());
+            return Ok(Some(output));
+        }
+        let Some(raw) = self.raw_value(name) else {
+            return Ok(None);
+        };
+
+        if let Some(start) = self.stack.iter().position(|n| n == name) {
+            let mut chain = self.stack[start..].to_vec();
+            chain.push(name.to_string());
+            return Err(TemplateError::Cycle(chain));
+        }
+
+        // Expand the referenced value in a fresh context
+        let path = std::mem::take(&mut self.path);
+        let issues = std::mem::take(&mut self.issues);
+        self.stack.push(name.to_string());
+        let value = self.expand(&raw, None);
+        self.stack.pop();
+        let nested = std::mem::replace(&mut self.issues, issues);
+        self.path = path;
+
+        if let Some(issue) = nested.into_iter().next() {
+            return Err(issue.error);
+        }
+
+        self.resolved.insert(name.to_string(), value.clone());
+        Ok(Some(value))
+    }
+
+    /// Read an allow-listed environment variable. Unset variables are
+    /// undefined, so `default` can supply a fallback.
+    fn env_var(&self, var: &str) -> Result<Option<serde_json::Value>, TemplateError> {
+        if !self.settings.env_allowed(var) {
+            return Err(TemplateError::EnvNotAllowed(var.to_string()));
+        }
+        Ok(std::env::var(var).ok().map(serde_json::Value::String))
+    }
+
+    /// Run an allow-listed command and return its trimmed stdout.
+    ///
+    /// The command is split on whitespace and run directly, not via a shell.
+    fn run_command(&self, command: &str) -> Result<serde_json::Value, TemplateError> {
+        let mut words = command.split_whitespace();
+        let program = words.next().unwrap_or_default();
+        if !self.settings.command_allowed(program) {
+            return Err(TemplateError::CommandNotAllowed(program.to_string()));
+        }
+
+        let failed = |message: String| TemplateError::Command {
+            command: command.to_string(),
+            message,
+        };
+        let output = Command::new(program)
+            .args(words)
+            .output()
+            .map_err(|e| failed(e.to_string()))?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(failed(match stderr.trim() {
+                "" => output.status.to_string(),
+                stderr => stderr.to_string(),
+            }));
+        }
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        Ok(serde_json::Value::String(stdout.trim_end().to_string()))
+    }
+
+    /// Look up the unexpanded value for a reference.
+    ///
+    /// Variables take precedence; otherwise `tool.key.sub` names a value in
+    /// a tool section (array elements are addressed by index), and
+    /// `tool.instance.key` a value in an instance's merged section.
+    fn raw_value(&self, name: &str) -> Option<serde_json::Value> {
+        if let Some(value) = self.variables.get(name) {
+            return Some(value.clone());
+        }
+
+        let mut segments = name.split('.').peekable();
+        let tool = segments.next()?;
+        let mut current = self.tools.get(tool)?;
+        if let Some(instance) = segments.peek()
+            && let Some(merged) = self.tools.get(&format!("{tool}.{instance}"))
+        {
+            current = merged;
+            segments.next();
+        }
+        let mut has_key = false;
+        for segment in segments {
+            current = match current {
+                serde_json::Value::Object(obj) => obj.get(segment)?,
+                serde_json::Value::Array(arr) => arr.get(segment.parse::<usize>().ok()?)?,
+                _ => return None,
+            };
+            has_key = true;
+        }
+        // A bare tool name is not a reference
+        has_key.then(|| current.clone())
+    }
+
+    fn report(&mut self, error: TemplateError) {
+        if self.strict {
+            self.issues.push(TemplateIssue {
+                path: self.path.clone(),
+                error,
+            });
+        }
+    }
+}
+
+/// Dotted reference name for a key inside a tool section.
+fn dotted(tool_name: &str, path: &[PathSegment]) -> String {
+    let mut out = tool_name.to_string();
+    for segment in path {
+        out.push('.');
+        match segment {
+            PathSegment::Key(k) => out.push_str(k),
+            PathSegment::Index(i) => out.push_str(&i.to_string()),
+        }
+    }
+    out
+}
+
+/// Render a scalar value for interpolation into a string.
+pub(crate) fn scalar_to_string(value: &serde_json::Value) -> Option<String> {
+    match value {
+        serde_json::Value::String(s) => Some(s.clone()),
+        serde_json::Value::Number(n) => Some(n.to_string()),
+        serde_json::Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    /// Expand a value against variables only, failing on any problem.
+    fn expand_variables(
+        value: &serde_json::Value,
+        vars: &HashMap<String, serde_json::Value>,
+    ) -> Result<serde_json::Value, Vec<TemplateIssue>> {
+        let mut resolver = Resolver::from_parts(vars.clone(), HashMap::new(), true);
+        let (expanded, issues) = resolver.expand_value(value);
+        if issues.is_empty() {
+            Ok(expanded)
+        } else {
+            Err(issues)
+        }
+    }
+
+    #[test]
+    fn expand_simple_variable() {
+        let mut vars = HashMap::new();
+        vars.insert("name".to_string(), serde_json::json!("my-project"));
+
+        let input = serde_json::json!({"title": "{{name}}"});
+        let output = expand_variables(&input, &vars).unwrap();
+
+        assert_eq!(output["title"], "my-project");
+    }
+
+    #[test]
+    fn expand_nested_variables() {
+        let mut vars = HashMap::new();
+        vars.insert("dir".to_string(), serde_json::json!("./assets"));
+
+        let input = serde_json::json!({
+            "paths": ["{{dir}}/a", "{{dir}}/b"]
+        });
+        let output = expand_variables(&input, &vars).unwrap();
+
+        assert_eq!(output["paths"][0], "./assets/a");
+        assert_eq!(output["paths"][1], "./assets/b");
+    }
+
+    #[test]
+    fn whole_placeholder_keeps_type() {
+        let vars = HashMap::from([
+            ("port".to_string(), serde_json::json!(8080)),
+            ("debug".to_string(), serde_json::json!(true)),
+            ("tags".to_string(), serde_json::json!(["a", "b"])),
+            ("db".to_string(), serde_json::json!({"host": "localhost"})),
+        ]);
+
+        let input = serde_json::json!({
+            "port": "{{port}}",
+            "debug": "{{debug}}",
+            "tags": "{{tags}}",
+            "db": "{{db}}",
+            "url": "http://localhost:{{port}}",
+        });
+        let output = expand_variables(&input, &vars).unwrap();
+
+        assert_eq!(output["port"], 8080);
+        assert_eq!(output["debug"], true);
+        assert_eq!(output["tags"], serde_json::json!(["a", "b"]));
+        assert_eq!(output["db"]["host"], "localhost");
+        assert_eq!(output["url"], "http://localhost:8080");
+
+        // Non-scalars can't be interpolated into a longer string
+        let input = serde_json::json!({"label": "tags: {{tags}}"});
+        let issues = expand_variables(&input, &vars).unwrap_err();
+        assert_eq!(
+            issues[0].error,
+            TemplateError::NotInterpolable("tags".to_string())
+        );
+    }
+
+    #[test]
+    fn undefined_variables_report_key_paths() {
+        let vars = HashMap::from([("assets".to_string(), serde_json::json!("./assets"))]);
+        let input = serde_json::json!({
+            "output": "{{asests}}/out",
+            "inputs": ["{{assets}}/a", "{{missing}}"],
+            "literal": "\\{{assets}}",
+        });
+
+        let issues = expand_variables(&input, &vars).unwrap_err();
+        let paths: Vec<_> = issues.iter().map(|i| i.key_path("dew")).collect();
+        assert_eq!(paths, ["dew.inputs[1]", "dew.output"]);
+        assert_eq!(issues[0].pointer(), "/inputs/1");
+
+        let mut lenient = Resolver::from_parts(vars, HashMap::new(), false);
+        let (output, issues) = lenient.expand_value(&input);
+        assert!(issues.is_empty());
+        assert_eq!(output["output"], "{{asests}}/out");
+        assert_eq!(output["inputs"][0], "./assets/a");
+        assert_eq!(output["literal"], "{{assets}}");
+    }
+
+    fn manifest_resolver(toml: &str) -> Resolver {
+        Resolver::new(&Manifest::parse(toml, Path::new(".")).unwrap(), true)
+    }
+
+    #[test]
+    fn variables_reference_variables() {
+        let mut resolver = manifest_resolver(
+            r#"
+            [project]
+            name = "game"
+
+            [variables]
+            assets = "./{{name}}-assets"
+            raw = "{{assets}}/raw"
+            port = 8080
+            copy = "{{port}}"
+
+            [siphon]
+            output = "{{raw}}"
+            port = "{{copy}}"
+        "#,
+        );
+
+        let (siphon, issues) = resolver.expand_tool("siphon");
+        assert!(issues.is_empty(), "{issues:?}");
+        assert_eq!(siphon["output"], "./game-assets/raw");
+        assert_eq!(siphon["port"], 8080);
+    }
+
+    #[test]
+    fn tools_reference_other_tools() {
+        let mut resolver = manifest_resolver(
+            r#"
+            [project]
+            name = "game"
+
+            [variables]
+            assets = "./assets"
+
+            [siphon]
+            output = "{{assets}}/raw"
+            formats = ["png", "ogg"]
+
+            [dew]
+            input = "{{siphon.output}}"
+            first = "{{siphon.formats.0}}"
+            formats = "{{siphon.formats}}"
+        "#,
+        );
+
+        let (dew, issues) = resolver.expand_tool("dew");
+        assert!(issues.is_empty(), "{issues:?}");
+        assert_eq!(dew["input"], "./assets/raw");
+        assert_eq!(dew["first"], "png");
+        assert_eq!(dew["formats"], serde_json::json!(["png", "ogg"]));
+    }
+
+    #[test]
+    fn filters_and_defaults() {
+        let mut resolver = manifest_resolver(
+            r#"
+            [project]
+            name = "My Game"
+
+            [variables]
+            port = 8080
+
+            [siphon]
+            crate_name = "{{name | snake}}"
+            output = "{{out | default: \"./build\" | join: \"raw\"}}"
+            port = "{{port | default: \"80\"}}"
+            fallback = "{{missing | default: \"80\"}}"
+        "#,
+        );
+
+        let (siphon, issues) = resolver.expand_tool("siphon");
+        assert!(issues.is_empty(), "{issues:?}");
+        assert_eq!(siphon["crate_name"], "my_game");
+        assert_eq!(siphon["output"], "./build/raw");
+        assert_eq!(siphon["port"], 8080);
+        assert_eq!(siphon["fallback"], "80");
+    }
+
+    #[test]
+    fn env_vars_need_allow_listing() {
+        let mut resolver = manifest_resolver(
+            r#"
+            [project]
+            name = "game"
+
+            [myenv]
+            allow_env = ["PATH", "MYENV_TEST_UNSET"]
+
+            [siphon]
+            path = "{{env.PATH}}"
+            ci = "{{env.MYENV_TEST_UNSET | default: \"false\"}}"
+            home = "{{env.HOME}}"
+        "#,
+        );
+
+        let (siphon, issues) = resolver.expand_tool("siphon");
+        assert_eq!(siphon["path"], std::env::var("PATH").unwrap());
+        assert_eq!(siphon["ci"], "false");
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].key_path("siphon"), "siphon.home");
+        assert_eq!(
+            issues[0].error,
+            TemplateError::EnvNotAllowed("HOME".to_string())
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn commands_need_allow_listing() {
+        let mut resolver = manifest_resolver(
+            r#"
+            [project]
+            name = "game"
+
+            [myenv]
+            allow_commands = ["echo", "false"]
+
+            [siphon]
+            rev = "{{cmd: echo abc123}}"
+            label = "build-{{cmd: echo abc123 | upper}}"
+            broken = "{{cmd: false}}"
+            denied = "{{cmd: git rev-parse HEAD}}"
+        "#,
+        );
+
+        let (siphon, issues) = resolver.expand_tool("siphon");
+        assert_eq!(siphon["rev"], "abc123");
+        assert_eq!(siphon["label"], "build-ABC123");
+        let errors: Vec<_> = issues.iter().map(|i| &i.error).collect();
+        assert!(matches!(errors[0], TemplateError::Command { command, .. } if command == "false"));
+        assert_eq!(
+            errors[1],
+            &TemplateError::CommandNotAllowed("git".to_string())
+        );
+    }
+
+    #[test]
+    fn reference_cycles_are_reported() {
+        let mut resolver = manifest_resolver(
+            r#"
+            [project]
+            name = "game"
+
+            [variables]
+            a = "{{b}}/x"
+            b = "{{a}}/y"
+
+            [siphon]
+            output = "{{dew.input}}"
+            vars = "{{a}}"
+
+            [dew]
+            input = "{{siphon.output}}"
+        "#,
+        );
+
+        let (_, issues) = resolver.expand_tool("siphon");
+        let errors: Vec<_> = issues.iter().map(|i| i.error.to_string()).collect();
+        assert_eq!(
+            errors,
+            [
+                "reference cycle: siphon.output -> dew.input -> siphon.output",
+                "reference cycle: a -> b -> a",
+            ]
+        );
+    }
+}
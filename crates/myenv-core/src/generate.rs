@@ -1,13 +1,20 @@
 //! Config file generation.
 
-use crate::manifest::Manifest;
+use crate::clean::{CleanResult, remove_generated};
+use crate::expand::Resolver;
+use crate::manifest::{ConfigTarget, Manifest, ToolSettings};
+use crate::merge::merge_config;
 use crate::schema::{ConfigFormat, SchemaError, SchemaProvider, ToolSchema};
-use std::collections::HashMap;
+use crate::state::{
+    GeneratedFile, GenerationState, StateError, load_snapshot, save_snapshot, write_atomic,
+};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 /// Result of generating a tool config.
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct GeneratedConfig {
     /// Tool name.
     pub tool: String,
@@ -18,16 +25,40 @@ pub struct GeneratedConfig {
 }
 
 /// Result of attempting to generate a tool config (may be skipped).
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
+#[serde(tag = "status", rename_all = "lowercase")]
 pub enum GenerateResult {
     /// Config was generated successfully.
     Generated(GeneratedConfig),
     /// Tool was skipped (e.g., --schema not supported).
     Skipped { tool: String, reason: String },
+    /// A previously generated config was removed because its tool left the
+    /// manifest or now writes elsewhere.
+    Pruned { tool: String, path: PathBuf },
+    /// Generation failed for this tool (only with `keep_going`).
+    Failed {
+        tool: String,
+        #[serde(serialize_with = "serialize_display")]
+        error: GenerateError,
+    },
+}
+
+impl GenerateResult {
+    /// Whether this tool failed to generate.
+    pub fn is_failed(&self) -> bool {
+        matches!(self, GenerateResult::Failed { .. })
+    }
+}
+
+fn serialize_display<S: serde::Serializer>(
+    value: &impl std::fmt::Display,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(value)
 }
 
 /// Preview of what would be generated (for diff mode).
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct ConfigPreview {
     /// Tool name.
     pub tool: String,
@@ -39,46 +70,390 @@ pub struct ConfigPreview {
     pub existing: Option<String>,
 }
 
+/// Result of previewing a tool config.
+#[derive(Debug, Serialize)]
+#[serde(tag = "status", rename_all = "lowercase")]
+pub enum PreviewResult {
+    /// What would be written.
+    Preview(ConfigPreview),
+    /// Previewing failed for this tool (only with `keep_going`).
+    Failed {
+        tool: String,
+        #[serde(serialize_with = "serialize_display")]
+        error: GenerateError,
+    },
+}
+
+impl PreviewResult {
+    /// The target this result is for.
+    pub fn tool(&self) -> &str {
+        match self {
+            PreviewResult::Preview(preview) => &preview.tool,
+            PreviewResult::Failed { tool, .. } => tool,
+        }
+    }
+}
+
+/// Options controlling config generation.
+#[derive(Debug, Clone, Default)]
+pub struct GenerateOptions {
+    /// Leave undefined or malformed `{{...}}` placeholders in the output
+    /// instead of failing.
+    pub allow_undefined: bool,
+    /// Overwrite configs that were edited since they were last generated.
+    pub force: bool,
+    /// Only process these tools (every tool in the manifest if empty).
+    pub tools: Vec<String>,
+    /// Skip these tools.
+    pub exclude: Vec<String>,
+    /// Keep generating the remaining tools after one fails, reporting the
+    /// failure as [`GenerateResult::Failed`] instead of returning an error.
+    pub keep_going: bool,
+}
+
+impl GenerateOptions {
+    /// Whether a tool or instance is selected by `tools` and `exclude`.
+    /// Naming a tool selects all of its instances.
+    pub fn selects(&self, id: &str) -> bool {
+        let target = ConfigTarget::from_id(id);
+        (self.tools.is_empty() || self.tools.iter().any(|t| target.matches(t)))
+            && !self.exclude.iter().any(|t| target.matches(t))
+    }
+
+    /// Check that every name in `tools` is a tool section or an instance.
+    pub fn check_selection(&self, manifest: &Manifest) -> Result<(), GenerateError> {
+        let targets = manifest.targets();
+        match self
+            .tools
+            .iter()
+            .find(|name| !targets.iter().any(|target| target.matches(name)))
+        {
+            Some(tool) => Err(GenerateError::UnknownTool(tool.clone())),
+            None => Ok(()),
+        }
+    }
+
+    /// Selected targets with their schemas, fetched up front so providers
+    /// can fetch them concurrently.
+    fn fetch_selected(
+        &self,
+        manifest: &Manifest,
+        provider: &dyn SchemaProvider,
+    ) -> Vec<(String, Result<ToolSchema, SchemaError>)> {
+        let ids: Vec<_> = manifest
+            .targets()
+            .into_iter()
+            .map(|target| target.id)
+            .filter(|id| self.selects(id))
+            .collect();
+        let schemas = fetch_schemas(provider, Some(manifest), &ids);
+        ids.into_iter().zip(schemas).collect()
+    }
+}
+
+/// Fetch schemas for several tools or instances and resolve where each
+/// config lives (see [`resolve_config_path`]).
+///
+/// Everything that reads or writes tool configs goes through this, so they
+/// all agree on where a config lives.
+pub(crate) fn fetch_schemas(
+    provider: &dyn SchemaProvider,
+    manifest: Option<&Manifest>,
+    ids: &[String],
+) -> Vec<Result<ToolSchema, SchemaError>> {
+    let targets: Vec<_> = ids.iter().map(|id| ConfigTarget::from_id(id)).collect();
+    let tools: Vec<_> = targets.iter().map(|target| target.tool.as_str()).collect();
+    let mut resolver = match manifest {
+        Some(manifest) => Resolver::new(manifest, true),
+        None => Resolver::from_parts(HashMap::new(), HashMap::new(), true),
+    };
+
+    provider
+        .fetch_all(&tools)
+        .into_iter()
+        .zip(&targets)
+        .map(|(schema, target)| {
+            let mut schema = schema?;
+            schema.config_path = resolve_config_path(manifest, &mut resolver, target, &schema)?;
+            Ok(schema)
+        })
+        .collect()
+}
+
+/// Where a tool's config lives: the `config_path` from its `_myenv`
+/// settings if set, otherwise the schema's, with `{{...}}` placeholders
+/// rendered.
+///
+/// Paths can use manifest variables and filters, plus `{{tool}}`,
+/// `{{instance}}` and `{{profile}}` (the active profile).
+fn resolve_config_path(
+    manifest: Option<&Manifest>,
+    resolver: &mut Resolver,
+    target: &ConfigTarget,
+    schema: &ToolSchema,
+) -> Result<PathBuf, SchemaError> {
+    let path = manifest
+        .and_then(|m| tool_settings(m, &target.id).config_path)
+        .unwrap_or_else(|| schema.config_path.clone());
+    let path = path.to_string_lossy();
+
+    let mut extra = HashMap::from([("tool", target.tool.clone())]);
+    if let Some(instance) = &target.instance {
+        extra.insert("instance", instance.clone());
+    }
+    if let Some(profile) = manifest.and_then(|m| m.active_profile.clone()) {
+        extra.insert("profile", profile);
+    }
+
+    resolver
+        .render(&path, &extra)
+        .map(PathBuf::from)
+        .map_err(|source| SchemaError::ConfigPath {
+            tool: target.id.clone(),
+            path: path.into_owned(),
+            source: Box::new(source),
+        })
+}
+
+/// Errors for targets that write the same config path as another target,
+/// keyed by target.
+fn shared_paths(
+    schemas: &[(String, Result<ToolSchema, SchemaError>)],
+) -> BTreeMap<String, GenerateError> {
+    let mut by_path: BTreeMap<&Path, Vec<&str>> = BTreeMap::new();
+    for (id, schema) in schemas {
+        if let Ok(schema) = schema {
+            by_path.entry(&schema.config_path).or_default().push(id);
+        }
+    }
+
+    let mut errors = BTreeMap::new();
+    for (path, ids) in by_path {
+        let [first, second, ..] = ids[..] else {
+            continue;
+        };
+        // Pair each target with the first one, and the first with the second
+        for id in ids {
+            let other = if id == first { second } else { id };
+            errors.insert(
+                id.to_string(),
+                GenerateError::SharedPath {
+                    first: first.to_string(),
+                    second: other.to_string(),
+                    path: path.to_path_buf(),
+                },
+            );
+        }
+    }
+    errors
+}
+
+/// Fail on the first shared config path, unless `keep_going` lets each
+/// target report its own.
+fn check_shared_paths(
+    schemas: &[(String, Result<ToolSchema, SchemaError>)],
+    keep_going: bool,
+) -> Result<BTreeMap<String, GenerateError>, GenerateError> {
+    let mut shared = shared_paths(schemas);
+    if !keep_going && let Some(error) = schemas.iter().find_map(|(id, _)| shared.remove(id)) {
+        return Err(error);
+    }
+    Ok(shared)
+}
+
 /// Errors that can occur during generation.
 #[derive(Debug, thiserror::Error)]
 pub enum GenerateError {
+    #[error("no [{0}] section in the manifest")]
+    UnknownTool(String),
+    #[error(
+        "'{first}' and '{second}' both write {}; put {{{{instance}}}} in the config_path",
+        path.display()
+    )]
+    SharedPath {
+        first: String,
+        second: String,
+        path: PathBuf,
+    },
     #[error("failed to fetch schema for '{0}': {1}")]
     SchemaFetch(String, SchemaError),
     #[error("tool '{tool}' config is invalid:\n{errors}")]
     ValidationFailed { tool: String, errors: String },
+    #[error("tool '{tool}' config has template errors:\n{errors}")]
+    Template { tool: String, errors: String },
     #[error("failed to create directory for '{0}': {1}")]
     CreateDir(String, std::io::Error),
     #[error("failed to write config for '{0}': {1}")]
     WriteConfig(String, std::io::Error),
     #[error("failed to serialize config for '{0}': {1}")]
     Serialize(String, String),
+    #[error("'{tool}' config {} was edited since it was last generated", path.display())]
+    ManuallyEdited { tool: String, path: PathBuf },
+    #[error("failed to merge into existing config for '{0}': {1}")]
+    Merge(String, String),
+    #[error("failed to remove config for '{0}': {1}")]
+    RemoveConfig(String, std::io::Error),
+    #[error("failed to update generation state: {0}")]
+    State(#[from] StateError),
+}
+
+/// A single schema violation in a tool config.
+#[derive(Debug, Clone, Serialize)]
+pub struct ValidationIssue {
+    /// JSON pointer to the offending value (empty for the config root).
+    pub path: String,
+    /// Description of the violation.
+    pub message: String,
 }
 
-/// Generate config files for all tools in the manifest.
+/// Outcome of validating a single tool config.
+#[derive(Debug)]
+pub enum ValidationOutcome {
+    /// Config matches the tool's schema.
+    Valid,
+    /// Config violates the tool's schema.
+    Invalid(Vec<ValidationIssue>),
+    /// Schema could not be fetched, so the config could not be checked.
+    SchemaUnavailable(SchemaError),
+}
+
+/// Validation diagnostics for a single tool.
+#[derive(Debug)]
+pub struct ToolValidation {
+    /// Tool name.
+    pub tool: String,
+    /// Path the config would be written to (if the schema was fetched).
+    pub config_path: Option<std::path::PathBuf>,
+    /// Validation outcome.
+    pub outcome: ValidationOutcome,
+}
+
+impl ToolValidation {
+    /// Whether the tool config passed validation.
+    pub fn is_valid(&self) -> bool {
+        matches!(self.outcome, ValidationOutcome::Valid)
+    }
+}
+
+/// Validate all tool configs in the manifest without writing anything.
+///
+/// Runs the same variable expansion and schema validation as
+/// [`generate_configs`], but keeps going after a failure so every broken
+/// tool is reported.
+pub fn validate_configs(
+    manifest: &Manifest,
+    provider: &dyn SchemaProvider,
+    options: &GenerateOptions,
+) -> Vec<ToolValidation> {
+    let mut resolver = Resolver::new(manifest, !options.allow_undefined);
+
+    options
+        .fetch_selected(manifest, provider)
+        .into_iter()
+        .map(|(tool_name, schema)| {
+            let schema = match schema {
+                Ok(s) => s,
+                Err(e) => {
+                    return ToolValidation {
+                        tool: tool_name.clone(),
+                        config_path: None,
+                        outcome: ValidationOutcome::SchemaUnavailable(e),
+                    };
+                }
+            };
+
+            let (expanded, template_issues) = resolver.expand_tool(&tool_name);
+            let mut issues: Vec<_> = template_issues
+                .into_iter()
+                .map(|issue| ValidationIssue {
+                    path: issue.pointer(),
+                    message: issue.error.to_string(),
+                })
+                .collect();
+            if issues.is_empty() {
+                issues = schema_issues(&expanded, &schema);
+            }
+            let outcome = if issues.is_empty() {
+                ValidationOutcome::Valid
+            } else {
+                ValidationOutcome::Invalid(issues)
+            };
+
+            ToolValidation {
+                tool: tool_name.clone(),
+                config_path: Some(schema.config_path),
+                outcome,
+            }
+        })
+        .collect()
+}
+
+/// Generate config files for the selected tools in the manifest.
+///
+/// Stale configs are only pruned for selected tools, so generating a subset
+/// leaves other tools' files alone.
 pub fn generate_configs(
     manifest: &Manifest,
     provider: &dyn SchemaProvider,
     base_dir: &Path,
+    options: &GenerateOptions,
 ) -> Result<Vec<GenerateResult>, GenerateError> {
+    options.check_selection(manifest)?;
     let mut results = Vec::new();
 
-    // Build variables map including project name
-    let mut vars: HashMap<String, String> = manifest
-        .variables
+    let mut resolver = Resolver::new(manifest, !options.allow_undefined);
+    let mut state = GenerationState::load(base_dir)?;
+    let previous: BTreeMap<_, _> = state
+        .tools
         .iter()
-        .filter_map(|(k, _)| manifest.get_variable(k).map(|val| (k.clone(), val)))
+        .filter(|(tool, _)| options.selects(tool))
+        .map(|(tool, file)| (tool.clone(), file.clone()))
         .collect();
-    vars.insert("name".to_string(), manifest.project.name.clone());
-    if let Some(version) = &manifest.project.version {
-        vars.insert("version".to_string(), version.clone());
-    }
 
-    for (tool_name, tool_config) in &manifest.tool_configs {
-        let result = generate_tool_config(tool_name, tool_config, &vars, provider, base_dir)?;
-        results.push(result);
-    }
+    let schemas = options.fetch_selected(manifest, provider);
+    let mut shared = check_shared_paths(&schemas, options.keep_going)?;
 
-    Ok(results)
+    // Record what was written even if a later tool fails
+    let outcome = schemas.into_iter().try_for_each(|(tool_name, schema)| {
+        let result = match shared.remove(&tool_name) {
+            Some(error) => Err(error),
+            None => generate_tool_config(
+                &tool_name,
+                &tool_settings(manifest, &tool_name),
+                schema,
+                &mut resolver,
+                base_dir,
+                options,
+                &mut state,
+            ),
+        };
+        match result {
+            Ok(result) => results.push(result),
+            Err(error) if options.keep_going => results.push(GenerateResult::Failed {
+                tool: tool_name,
+                error,
+            }),
+            Err(e) => return Err(e),
+        }
+        Ok(())
+    });
+    // Only prune after a fully successful run, so a tool that failed under
+    // keep_going can't lose its config
+    let outcome = outcome.and_then(|()| {
+        if results.iter().any(GenerateResult::is_failed) {
+            return Ok(());
+        }
+        prune_stale(
+            manifest,
+            base_dir,
+            &previous,
+            &mut state,
+            options.force,
+            &mut results,
+        )
+    });
+    state.save(base_dir)?;
+    outcome.map(|()| results)
 }
 
 /// Preview what configs would be generated (for diff mode).
@@ -86,23 +461,32 @@ pub fn preview_configs(
     manifest: &Manifest,
     provider: &dyn SchemaProvider,
     base_dir: &Path,
-) -> Result<Vec<ConfigPreview>, GenerateError> {
+    options: &GenerateOptions,
+) -> Result<Vec<PreviewResult>, GenerateError> {
+    options.check_selection(manifest)?;
     let mut previews = Vec::new();
 
-    // Build variables map including project name
-    let mut vars: HashMap<String, String> = manifest
-        .variables
-        .iter()
-        .filter_map(|(k, _)| manifest.get_variable(k).map(|val| (k.clone(), val)))
-        .collect();
-    vars.insert("name".to_string(), manifest.project.name.clone());
-    if let Some(version) = &manifest.project.version {
-        vars.insert("version".to_string(), version.clone());
-    }
+    let mut resolver = Resolver::new(manifest, !options.allow_undefined);
+
+    let schemas = options.fetch_selected(manifest, provider);
+    let mut shared = check_shared_paths(&schemas, options.keep_going)?;
 
-    for (tool_name, tool_config) in &manifest.tool_configs {
-        let preview = preview_tool_config(tool_name, tool_config, &vars, provider, base_dir)?;
-        previews.push(preview);
+    for (tool_name, schema) in schemas {
+        let preview = match shared.remove(&tool_name) {
+            Some(error) => Err(error),
+            None => {
+                let settings = tool_settings(manifest, &tool_name);
+                preview_tool_config(&tool_name, &settings, schema, &mut resolver, base_dir)
+            }
+        };
+        match preview {
+            Ok(preview) => previews.push(PreviewResult::Preview(preview)),
+            Err(error) if options.keep_going => previews.push(PreviewResult::Failed {
+                tool: tool_name,
+                error,
+            }),
+            Err(e) => return Err(e),
+        }
     }
 
     Ok(previews)
@@ -111,26 +495,29 @@ pub fn preview_configs(
 /// Preview config for a single tool.
 fn preview_tool_config(
     tool_name: &str,
-    config: &toml::Value,
-    vars: &HashMap<String, String>,
-    provider: &dyn SchemaProvider,
+    settings: &ToolSettings,
+    schema: Result<ToolSchema, SchemaError>,
+    resolver: &mut Resolver,
     base_dir: &Path,
 ) -> Result<ConfigPreview, GenerateError> {
-    // Fetch schema
-    let schema = provider
-        .fetch(tool_name)
-        .map_err(|e| GenerateError::SchemaFetch(tool_name.to_string(), e))?;
+    let schema = schema.map_err(|e| GenerateError::SchemaFetch(tool_name.to_string(), e))?;
 
-    // Convert config to JSON for validation and variable expansion
-    let config_json = toml_to_json(config);
-    let expanded = expand_variables(&config_json, vars);
+    // Expand variables and cross-tool references
+    let expanded = expand_tool_config(tool_name, resolver)?;
 
     // Validate against schema
     validate_config(tool_name, &expanded, &schema)?;
 
     // Serialize without writing
     let config_path = base_dir.join(&schema.config_path);
-    let content = serialize_config(tool_name, &expanded, schema.format)?;
+    let content = render_config(
+        tool_name,
+        settings,
+        base_dir,
+        &config_path,
+        &expanded,
+        schema.format,
+    )?;
 
     // Read existing content if present
     let existing = fs::read_to_string(&config_path).ok();
@@ -146,16 +533,23 @@ fn preview_tool_config(
 /// Generate config for a single tool.
 fn generate_tool_config(
     tool_name: &str,
-    config: &toml::Value,
-    vars: &HashMap<String, String>,
-    provider: &dyn SchemaProvider,
+    settings: &ToolSettings,
+    schema: Result<ToolSchema, SchemaError>,
+    resolver: &mut Resolver,
     base_dir: &Path,
+    options: &GenerateOptions,
+    state: &mut GenerationState,
 ) -> Result<GenerateResult, GenerateError> {
-    use crate::schema::SchemaError;
-
-    // Fetch schema - skip gracefully if tool doesn't support --schema
-    let schema = match provider.fetch(tool_name) {
+    // Skip gracefully if the tool doesn't support --schema and has no
+    // schema file
+    let schema = match schema {
         Ok(s) => s,
+        Err(e @ SchemaError::NoSchemaFile { .. }) => {
+            return Ok(GenerateResult::Skipped {
+                tool: tool_name.to_string(),
+                reason: e.to_string(),
+            });
+        }
         Err(SchemaError::ToolNotFound(t)) => {
             return Ok(GenerateResult::Skipped {
                 tool: tool_name.to_string(),
@@ -171,16 +565,29 @@ fn generate_tool_config(
         Err(e) => return Err(GenerateError::SchemaFetch(tool_name.to_string(), e)),
     };
 
-    // Convert config to JSON for validation and variable expansion
-    let config_json = toml_to_json(config);
-    let expanded = expand_variables(&config_json, vars);
+    // Expand variables and cross-tool references
+    let expanded = expand_tool_config(tool_name, resolver)?;
 
     // Validate against schema
     validate_config(tool_name, &expanded, &schema)?;
 
     // Write config file
     let config_path = base_dir.join(&schema.config_path);
-    write_config(tool_name, &config_path, &expanded, schema.format)?;
+    let contents = render_config(
+        tool_name,
+        settings,
+        base_dir,
+        &config_path,
+        &expanded,
+        schema.format,
+    )?;
+    // Merged files are expected to carry hand edits outside managed keys
+    if !options.force && !settings.merge {
+        check_unedited(tool_name, base_dir, &config_path, &contents, state)?;
+    }
+    write_config(tool_name, &config_path, &contents)?;
+    state.record(tool_name, base_dir, &config_path, contents.as_bytes());
+    save_snapshot(base_dir, tool_name, &expanded)?;
 
     Ok(GenerateResult::Generated(GeneratedConfig {
         tool: tool_name.to_string(),
@@ -190,22 +597,16 @@ fn generate_tool_config(
 }
 
 /// Validate config against schema.
-fn validate_config(
+pub(crate) fn validate_config(
     tool_name: &str,
     config: &serde_json::Value,
     schema: &ToolSchema,
 ) -> Result<(), GenerateError> {
-    let validator =
-        jsonschema::validator_for(&schema.schema).map_err(|e| GenerateError::ValidationFailed {
-            tool: tool_name.to_string(),
-            errors: format!("invalid schema: {e}"),
-        })?;
-
-    let errors: Vec<_> = validator.iter_errors(config).collect();
-    if !errors.is_empty() {
-        let error_messages: Vec<_> = errors
+    let issues = schema_issues(config, schema);
+    if !issues.is_empty() {
+        let error_messages: Vec<_> = issues
             .iter()
-            .map(|e| format!("  - {}: {}", e.instance_path, e))
+            .map(|issue| format!("  - {}: {}", issue.path, issue.message))
             .collect();
         return Err(GenerateError::ValidationFailed {
             tool: tool_name.to_string(),
@@ -216,6 +617,29 @@ fn validate_config(
     Ok(())
 }
 
+/// Collect all schema violations for a config.
+///
+/// An unusable schema is reported as a single issue at the config root.
+fn schema_issues(config: &serde_json::Value, schema: &ToolSchema) -> Vec<ValidationIssue> {
+    let validator = match jsonschema::validator_for(&schema.schema) {
+        Ok(v) => v,
+        Err(e) => {
+            return vec![ValidationIssue {
+                path: String::new(),
+                message: format!("invalid schema: {e}"),
+            }];
+        }
+    };
+
+    validator
+        .iter_errors(config)
+        .map(|e| ValidationIssue {
+            path: e.instance_path.to_string(),
+            message: e.to_string(),
+        })
+        .collect()
+}
+
 /// Serialize config to string in the specified format.
 fn serialize_config(
     tool_name: &str,
@@ -235,12 +659,104 @@ fn serialize_config(
     }
 }
 
-/// Write config to file in the specified format.
-fn write_config(
+/// A tool's `_myenv` settings. These were validated when the manifest was
+/// loaded.
+pub(crate) fn tool_settings(manifest: &Manifest, tool_name: &str) -> ToolSettings {
+    manifest.tool_settings(tool_name).unwrap_or_default()
+}
+
+/// Produce the file contents for a config, merging into the existing file
+/// when the tool is in merge mode.
+///
+/// Merging removes keys from the tool's last snapshot that `config` no
+/// longer has.
+pub(crate) fn render_config(
     tool_name: &str,
+    settings: &ToolSettings,
+    base_dir: &Path,
     path: &Path,
     config: &serde_json::Value,
     format: ConfigFormat,
+) -> Result<String, GenerateError> {
+    if settings.merge
+        && let Ok(existing) = fs::read_to_string(path)
+    {
+        let previous = load_snapshot(base_dir, tool_name)?;
+        return merge_config(&existing, config, previous.as_ref(), format)
+            .map_err(|e| GenerateError::Merge(tool_name.to_string(), e));
+    }
+    serialize_config(tool_name, config, format)
+}
+
+/// Remove configs generated for tools that left the manifest or whose
+/// config path changed. Edited files are left in place and reported.
+fn prune_stale(
+    manifest: &Manifest,
+    base_dir: &Path,
+    previous: &BTreeMap<String, GeneratedFile>,
+    state: &mut GenerationState,
+    force: bool,
+    results: &mut Vec<GenerateResult>,
+) -> Result<(), GenerateError> {
+    for (tool, old) in previous {
+        let removed = !manifest.has_target(tool);
+        let moved = state
+            .tools
+            .get(tool)
+            .is_some_and(|new| new.path != old.path);
+        if !removed && !moved {
+            continue;
+        }
+
+        match remove_generated(tool, old, base_dir, false, force)? {
+            Some(CleanResult::Edited { tool, path }) => {
+                results.push(GenerateResult::Skipped {
+                    reason: format!(
+                        "stale config {} was edited since it was generated; not removing it",
+                        path.display()
+                    ),
+                    tool,
+                });
+                continue;
+            }
+            Some(CleanResult::Removed { tool, path }) => {
+                results.push(GenerateResult::Pruned { tool, path });
+            }
+            None => {}
+        }
+        if removed {
+            state.forget(tool, base_dir);
+        }
+    }
+    Ok(())
+}
+
+/// Refuse to overwrite a generated config that was edited by hand.
+fn check_unedited(
+    tool_name: &str,
+    base_dir: &Path,
+    path: &Path,
+    contents: &str,
+    state: &GenerationState,
+) -> Result<(), GenerateError> {
+    let Ok(existing) = fs::read(path) else {
+        return Ok(());
+    };
+    // Edits that match the new output are harmless
+    if existing != contents.as_bytes() && state.is_modified(tool_name, base_dir, path, &existing) {
+        return Err(GenerateError::ManuallyEdited {
+            tool: tool_name.to_string(),
+            path: path.to_path_buf(),
+        });
+    }
+    Ok(())
+}
+
+/// Write a config atomically.
+pub(crate) fn write_config(
+    tool_name: &str,
+    path: &Path,
+    contents: &str,
 ) -> Result<(), GenerateError> {
     // Create parent directories
     if let Some(parent) = path.parent() {
@@ -248,13 +764,12 @@ fn write_config(
             .map_err(|e| GenerateError::CreateDir(tool_name.to_string(), e))?;
     }
 
-    let contents = serialize_config(tool_name, config, format)?;
-
-    fs::write(path, contents).map_err(|e| GenerateError::WriteConfig(tool_name.to_string(), e))
+    write_atomic(path, contents.as_bytes())
+        .map_err(|e| GenerateError::WriteConfig(tool_name.to_string(), e))
 }
 
 /// Convert TOML value to JSON value.
-fn toml_to_json(value: &toml::Value) -> serde_json::Value {
+pub(crate) fn toml_to_json(value: &toml::Value) -> serde_json::Value {
     match value {
         toml::Value::String(s) => serde_json::Value::String(s.clone()),
         toml::Value::Integer(i) => serde_json::Value::Number((*i).into()),
@@ -275,7 +790,7 @@ fn toml_to_json(value: &toml::Value) -> serde_json::Value {
 }
 
 /// Convert JSON value to TOML value.
-fn json_to_toml(value: &serde_json::Value) -> toml::Value {
+pub(crate) fn json_to_toml(value: &serde_json::Value) -> toml::Value {
     match value {
         serde_json::Value::Null => toml::Value::String("null".to_string()),
         serde_json::Value::Bool(b) => toml::Value::Boolean(*b),
@@ -300,60 +815,431 @@ fn json_to_toml(value: &serde_json::Value) -> toml::Value {
     }
 }
 
-/// Expand {{variable}} placeholders in all string values.
-fn expand_variables(
-    value: &serde_json::Value,
-    vars: &HashMap<String, String>,
-) -> serde_json::Value {
-    match value {
-        serde_json::Value::String(s) => {
-            let mut result = s.clone();
-            for (key, val) in vars {
-                result = result.replace(&format!("{{{{{key}}}}}"), val);
-            }
-            serde_json::Value::String(result)
-        }
-        serde_json::Value::Array(arr) => {
-            serde_json::Value::Array(arr.iter().map(|v| expand_variables(v, vars)).collect())
-        }
-        serde_json::Value::Object(obj) => {
-            let map = obj
-                .iter()
-                .map(|(k, v)| (k.clone(), expand_variables(v, vars)))
-                .collect();
-            serde_json::Value::Object(map)
-        }
-        other => other.clone(),
+/// Expand placeholders in a tool config, reporting every template error.
+pub(crate) fn expand_tool_config(
+    tool_name: &str,
+    resolver: &mut Resolver,
+) -> Result<serde_json::Value, GenerateError> {
+    let (expanded, issues) = resolver.expand_tool(tool_name);
+
+    if !issues.is_empty() {
+        let error_messages: Vec<_> = issues
+            .iter()
+            .map(|issue| format!("  - {}: {}", issue.key_path(tool_name), issue.error))
+            .collect();
+        return Err(GenerateError::Template {
+            tool: tool_name.to_string(),
+            errors: error_messages.join("\n"),
+        });
     }
+
+    Ok(expanded)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::collections::HashMap;
+
+    struct MockProvider(HashMap<String, ToolSchema>);
+
+    impl SchemaProvider for MockProvider {
+        fn fetch(&self, tool: &str) -> Result<ToolSchema, SchemaError> {
+            self.0
+                .get(tool)
+                .cloned()
+                .ok_or_else(|| SchemaError::ToolNotFound(tool.to_string()))
+        }
+    }
+
+    fn string_schema(config_path: &str) -> ToolSchema {
+        ToolSchema {
+            config_path: config_path.into(),
+            format: ConfigFormat::Toml,
+            schema: serde_json::json!({
+                "type": "object",
+                "properties": { "output": { "type": "string" } },
+                "required": ["output"]
+            }),
+        }
+    }
 
     #[test]
-    fn expand_simple_variable() {
-        let mut vars = HashMap::new();
-        vars.insert("name".to_string(), "my-project".to_string());
+    fn validate_reports_every_failing_tool() {
+        let manifest = Manifest::parse(
+            r#"
+            [project]
+            name = "test"
+
+            [variables]
+            out = "./out"
+
+            [good]
+            output = "{{out}}/good"
 
-        let input = serde_json::json!({"title": "{{name}}"});
-        let output = expand_variables(&input, &vars);
+            [bad]
+            output = 5
 
-        assert_eq!(output["title"], "my-project");
+            [missing]
+            output = "./missing"
+        "#,
+            Path::new("."),
+        )
+        .unwrap();
+
+        let provider = MockProvider(HashMap::from([
+            ("good".to_string(), string_schema(".good/config.toml")),
+            ("bad".to_string(), string_schema(".bad/config.toml")),
+        ]));
+
+        let results = validate_configs(&manifest, &provider, &GenerateOptions::default());
+        assert_eq!(results.len(), 3);
+
+        let by_tool = |name: &str| results.iter().find(|r| r.tool == name).unwrap();
+        assert!(by_tool("good").is_valid());
+        match &by_tool("bad").outcome {
+            ValidationOutcome::Invalid(issues) => {
+                assert_eq!(issues.len(), 1);
+                assert_eq!(issues[0].path, "/output");
+            }
+            other => panic!("expected invalid, got {other:?}"),
+        }
+        assert!(matches!(
+            by_tool("missing").outcome,
+            ValidationOutcome::SchemaUnavailable(SchemaError::ToolNotFound(_))
+        ));
     }
 
     #[test]
-    fn expand_nested_variables() {
-        let mut vars = HashMap::new();
-        vars.insert("dir".to_string(), "./assets".to_string());
+    fn generate_refuses_to_overwrite_edits() {
+        let dir = tempfile::tempdir().unwrap();
+        let manifest = Manifest::parse(
+            r#"
+            [project]
+            name = "test"
 
-        let input = serde_json::json!({
-            "paths": ["{{dir}}/a", "{{dir}}/b"]
-        });
-        let output = expand_variables(&input, &vars);
+            [dew]
+            output = "./out"
+        "#,
+            Path::new("."),
+        )
+        .unwrap();
+        let provider = MockProvider(HashMap::from([(
+            "dew".to_string(),
+            string_schema(".dew/config.toml"),
+        )]));
+        let options = GenerateOptions::default();
+
+        generate_configs(&manifest, &provider, dir.path(), &options).unwrap();
+        let state = GenerationState::load(dir.path()).unwrap();
+        assert_eq!(state.tools["dew"].path, Path::new(".dew/config.toml"));
+
+        // Regenerating unchanged output is fine
+        generate_configs(&manifest, &provider, dir.path(), &options).unwrap();
+
+        let config = dir.path().join(".dew/config.toml");
+        fs::write(&config, "output = \"./hand-edited\"\n").unwrap();
+        let err = generate_configs(&manifest, &provider, dir.path(), &options).unwrap_err();
+        assert!(
+            matches!(&err, GenerateError::ManuallyEdited { tool, path } if tool == "dew" && *path == config)
+        );
+        assert_eq!(
+            fs::read_to_string(&config).unwrap(),
+            "output = \"./hand-edited\"\n"
+        );
+
+        let force = GenerateOptions {
+            force: true,
+            ..Default::default()
+        };
+        generate_configs(&manifest, &provider, dir.path(), &force).unwrap();
+        assert_eq!(fs::read_to_string(&config).unwrap(), "output = \"./out\"\n");
+    }
+
+    #[test]
+    fn generate_prunes_removed_tools() {
+        let dir = tempfile::tempdir().unwrap();
+        let provider = MockProvider(HashMap::from([
+            ("dew".to_string(), string_schema(".dew/config.toml")),
+            ("siphon".to_string(), string_schema(".siphon/config.toml")),
+        ]));
+        let options = GenerateOptions::default();
+
+        let both = Manifest::parse(
+            r#"
+            [project]
+            name = "test"
+
+            [dew]
+            output = "./out"
+
+            [siphon]
+            output = "./raw"
+        "#,
+            Path::new("."),
+        )
+        .unwrap();
+        generate_configs(&both, &provider, dir.path(), &options).unwrap();
+        fs::write(dir.path().join(".siphon/mine.toml"), "").unwrap();
+
+        let dew_only = Manifest::parse(
+            r#"
+            [project]
+            name = "test"
+
+            [dew]
+            output = "./out"
+        "#,
+            Path::new("."),
+        )
+        .unwrap();
+        let results = generate_configs(&dew_only, &provider, dir.path(), &options).unwrap();
+        assert!(
+            results
+                .iter()
+                .any(|r| matches!(r, GenerateResult::Pruned { tool, .. } if tool == "siphon"))
+        );
+        assert!(!dir.path().join(".siphon/config.toml").exists());
+        assert!(dir.path().join(".siphon/mine.toml").exists());
+
+        let state = GenerationState::load(dir.path()).unwrap();
+        assert!(!state.tools.contains_key("siphon"));
+    }
+
+    #[test]
+    fn generate_only_selected_tools() {
+        let dir = tempfile::tempdir().unwrap();
+        let manifest = Manifest::parse(
+            r#"
+            [project]
+            name = "test"
+
+            [dew]
+            output = "./out"
+
+            [siphon]
+            output = "./raw"
+
+            [broken]
+            output = "./never"
+        "#,
+            Path::new("."),
+        )
+        .unwrap();
+        // `broken` has no schema, so generating everything would fail on it
+        let provider = MockProvider(HashMap::from([
+            ("dew".to_string(), string_schema(".dew/config.toml")),
+            ("siphon".to_string(), string_schema(".siphon/config.toml")),
+        ]));
+
+        let only_dew = GenerateOptions {
+            tools: vec!["dew".to_string()],
+            ..Default::default()
+        };
+        let results = generate_configs(&manifest, &provider, dir.path(), &only_dew).unwrap();
+        assert_eq!(results.len(), 1);
+        assert!(dir.path().join(".dew/config.toml").exists());
+        assert!(!dir.path().join(".siphon/config.toml").exists());
+
+        let no_broken = GenerateOptions {
+            exclude: vec!["broken".to_string(), "dew".to_string()],
+            ..Default::default()
+        };
+        let previews = preview_configs(&manifest, &provider, dir.path(), &no_broken).unwrap();
+        let tools: Vec<_> = previews.iter().map(PreviewResult::tool).collect();
+        assert_eq!(tools, ["siphon"]);
+
+        let typo = GenerateOptions {
+            tools: vec!["dwe".to_string()],
+            ..Default::default()
+        };
+        let err = generate_configs(&manifest, &provider, dir.path(), &typo).unwrap_err();
+        assert!(matches!(err, GenerateError::UnknownTool(tool) if tool == "dwe"));
+
+        // Generating a subset doesn't prune other tools' configs
+        let without_dew = Manifest::parse(
+            r#"
+            [project]
+            name = "test"
+
+            [siphon]
+            output = "./raw"
+        "#,
+            Path::new("."),
+        )
+        .unwrap();
+        let only_siphon = GenerateOptions {
+            tools: vec!["siphon".to_string()],
+            ..Default::default()
+        };
+        generate_configs(&without_dew, &provider, dir.path(), &only_siphon).unwrap();
+        assert!(dir.path().join(".dew/config.toml").exists());
+    }
+
+    #[test]
+    fn config_path_override_moves_the_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let manifest = Manifest::parse(
+            r#"
+            [project]
+            name = "test"
+
+            [dew]
+            output = "./out"
 
-        assert_eq!(output["paths"][0], "./assets/a");
-        assert_eq!(output["paths"][1], "./assets/b");
+            [dew._myenv]
+            config_path = "config/dew.toml"
+        "#,
+            Path::new("."),
+        )
+        .unwrap();
+        let provider = MockProvider(HashMap::from([(
+            "dew".to_string(),
+            string_schema(".dew/config.toml"),
+        )]));
+        let options = GenerateOptions::default();
+
+        let previews = preview_configs(&manifest, &provider, dir.path(), &options).unwrap();
+        let [PreviewResult::Preview(preview)] = &previews[..] else {
+            panic!("expected one preview, got {previews:?}");
+        };
+        assert_eq!(preview.path, dir.path().join("config/dew.toml"));
+
+        generate_configs(&manifest, &provider, dir.path(), &options).unwrap();
+        assert!(dir.path().join("config/dew.toml").exists());
+        assert!(!dir.path().join(".dew/config.toml").exists());
+        let state = GenerationState::load(dir.path()).unwrap();
+        assert_eq!(state.tools["dew"].path, Path::new("config/dew.toml"));
+    }
+
+    #[test]
+    fn keep_going_reports_every_failure() {
+        let dir = tempfile::tempdir().unwrap();
+        let manifest = Manifest::parse(
+            r#"
+            [project]
+            name = "test"
+
+            [bad]
+            output = 5
+
+            [dew]
+            output = "./out"
+
+            [missing]
+            output = "./missing"
+        "#,
+            Path::new("."),
+        )
+        .unwrap();
+        let provider = MockProvider(HashMap::from([
+            ("bad".to_string(), string_schema(".bad/config.toml")),
+            ("dew".to_string(), string_schema(".dew/config.toml")),
+        ]));
+
+        // By default the first failure stops generation
+        let err = generate_configs(
+            &manifest,
+            &provider,
+            dir.path(),
+            &GenerateOptions::default(),
+        )
+        .unwrap_err();
+        assert!(matches!(err, GenerateError::ValidationFailed { .. }));
+        assert!(!dir.path().join(".dew/config.toml").exists());
+
+        let options = GenerateOptions {
+            keep_going: true,
+            ..Default::default()
+        };
+        let results = generate_configs(&manifest, &provider, dir.path(), &options).unwrap();
+        let summary: Vec<_> = results
+            .iter()
+            .map(|r| match r {
+                GenerateResult::Generated(config) => format!("generated {}", config.tool),
+                GenerateResult::Skipped { tool, .. } => format!("skipped {tool}"),
+                GenerateResult::Pruned { tool, .. } => format!("pruned {tool}"),
+                GenerateResult::Failed { tool, .. } => format!("failed {tool}"),
+            })
+            .collect();
+        assert_eq!(summary, ["failed bad", "generated dew", "skipped missing"]);
+        assert!(dir.path().join(".dew/config.toml").exists());
+
+        let json = serde_json::to_value(&results[0]).unwrap();
+        assert_eq!(json["status"], "failed");
+        assert!(
+            json["error"]
+                .as_str()
+                .unwrap()
+                .contains("'bad' config is invalid")
+        );
+
+        // Stale configs are only pruned once every tool succeeds
+        let without_dew = Manifest::parse(
+            r#"
+            [project]
+            name = "test"
+
+            [bad]
+            output = 5
+        "#,
+            Path::new("."),
+        )
+        .unwrap();
+        let results = generate_configs(&without_dew, &provider, dir.path(), &options).unwrap();
+        assert_eq!(results.len(), 1);
+        assert!(results[0].is_failed());
+        assert!(dir.path().join(".dew/config.toml").exists());
+    }
+
+    #[test]
+    fn merge_mode_keeps_unmanaged_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = dir.path().join(".dew/config.toml");
+        fs::create_dir_all(config.parent().unwrap()).unwrap();
+        fs::write(&config, "# local tweaks\nthreads = 4\noutput = \"./old\"\n").unwrap();
+
+        let manifest = Manifest::parse(
+            r#"
+            [project]
+            name = "test"
+
+            [dew]
+            output = "./out"
+
+            [dew._myenv]
+            merge = true
+        "#,
+            Path::new("."),
+        )
+        .unwrap();
+        let provider = MockProvider(HashMap::from([(
+            "dew".to_string(),
+            string_schema(".dew/config.toml"),
+        )]));
+
+        generate_configs(
+            &manifest,
+            &provider,
+            dir.path(),
+            &GenerateOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            fs::read_to_string(&config).unwrap(),
+            "# local tweaks\nthreads = 4\noutput = \"./out\"\n"
+        );
+
+        // Hand edits in merge mode are not treated as conflicts
+        fs::write(&config, "# local tweaks\nthreads = 8\noutput = \"./out\"\n").unwrap();
+        generate_configs(
+            &manifest,
+            &provider,
+            dir.path(),
+            &GenerateOptions::default(),
+        )
+        .unwrap();
+        assert!(fs::read_to_string(&config).unwrap().contains("threads = 8"));
     }
 
     #[test]
@@ -370,4 +1256,211 @@ mod tests {
 
         assert_eq!(toml_value, back);
     }
+
+    #[test]
+    fn generate_tool_instances() {
+        let dir = tempfile::tempdir().unwrap();
+        let manifest = Manifest::parse(
+            r#"
+            [project]
+            name = "test"
+
+            [dew]
+            output = "./out/default"
+
+            [dew._myenv]
+            config_path = ".dew/{{instance}}.toml"
+
+            [dew.instances.sprites]
+            output = "./out/sprites"
+
+            [dew.instances.audio]
+
+            [lotus]
+            output = "{{dew.sprites.output}}"
+        "#,
+            Path::new("."),
+        )
+        .unwrap();
+        let provider = MockProvider(HashMap::from([
+            ("dew".to_string(), string_schema(".dew/config.toml")),
+            ("lotus".to_string(), string_schema("lotus.toml")),
+        ]));
+
+        let results = generate_configs(
+            &manifest,
+            &provider,
+            dir.path(),
+            &GenerateOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(results.len(), 3);
+        let read = |rel: &str| fs::read_to_string(dir.path().join(rel)).unwrap();
+        assert_eq!(read(".dew/sprites.toml"), "output = \"./out/sprites\"\n");
+        assert_eq!(read(".dew/audio.toml"), "output = \"./out/default\"\n");
+        assert_eq!(read("lotus.toml"), "output = \"./out/sprites\"\n");
+        let state = GenerationState::load(dir.path()).unwrap();
+        let tools: Vec<_> = state.tools.keys().collect();
+        assert_eq!(tools, ["dew.audio", "dew.sprites", "lotus"]);
+
+        // One instance can be selected on its own
+        let only_audio = GenerateOptions {
+            tools: vec!["dew.audio".to_string()],
+            ..Default::default()
+        };
+        let previews = preview_configs(&manifest, &provider, dir.path(), &only_audio).unwrap();
+        let tools: Vec<_> = previews.iter().map(PreviewResult::tool).collect();
+        assert_eq!(tools, ["dew.audio"]);
+
+        // Without {{instance}} every instance would write the same file
+        let shared = Manifest::parse(
+            r#"
+            [project]
+            name = "test"
+
+            [dew.instances.sprites]
+            output = "./a"
+
+            [dew.instances.audio]
+            output = "./b"
+
+            [lotus]
+            output = "./c"
+        "#,
+            Path::new("."),
+        )
+        .unwrap();
+        let err = generate_configs(&shared, &provider, dir.path(), &GenerateOptions::default())
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            GenerateError::SharedPath { first, second, .. }
+                if first == "dew.audio" && second == "dew.sprites"
+        ));
+
+        // With keep_going both instances fail and other tools still generate
+        let keep_going = GenerateOptions {
+            keep_going: true,
+            ..Default::default()
+        };
+        let results = generate_configs(&shared, &provider, dir.path(), &keep_going).unwrap();
+        let summary: Vec<_> = results
+            .iter()
+            .map(|r| match r {
+                GenerateResult::Generated(config) => format!("generated {}", config.tool),
+                GenerateResult::Failed { tool, .. } => format!("failed {tool}"),
+                other => panic!("unexpected result {other:?}"),
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            ["failed dew.audio", "failed dew.sprites", "generated lotus"]
+        );
+        assert_eq!(
+            fs::read_to_string(dir.path().join("lotus.toml")).unwrap(),
+            "output = \"./c\"\n"
+        );
+
+        let previews = preview_configs(&shared, &provider, dir.path(), &keep_going).unwrap();
+        let summary: Vec<_> = previews
+            .iter()
+            .map(|p| match p {
+                PreviewResult::Preview(preview) => format!("preview {}", preview.tool),
+                PreviewResult::Failed { tool, .. } => format!("failed {tool}"),
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            ["failed dew.audio", "failed dew.sprites", "preview lotus"]
+        );
+    }
+
+    #[test]
+    fn config_paths_are_templated() {
+        let dir = tempfile::tempdir().unwrap();
+        let manifest = Manifest::parse(
+            r#"
+            [project]
+            name = "test"
+
+            [variables]
+            build = "build"
+
+            [dew]
+            output = "./out"
+
+            [dew._myenv]
+            config_path = '{{build}}/{{profile | default: "dev"}}/{{tool}}.toml'
+
+            [lotus]
+            output = "./web"
+
+            [profile.ci.variables]
+            build = "target"
+        "#,
+            Path::new("."),
+        )
+        .unwrap();
+        let provider = MockProvider(HashMap::from([
+            ("dew".to_string(), string_schema(".dew/config.toml")),
+            ("lotus".to_string(), string_schema("{{build}}/lotus.toml")),
+        ]));
+        let options = GenerateOptions::default();
+
+        generate_configs(&manifest, &provider, dir.path(), &options).unwrap();
+        assert!(dir.path().join("build/dev/dew.toml").exists());
+        assert!(dir.path().join("build/lotus.toml").exists());
+
+        // Pull reads from the same place
+        let pulled =
+            crate::pull::pull_configs(&["dew".to_string()], Some(&manifest), &provider, dir.path())
+                .unwrap();
+        assert_eq!(pulled[0].path, dir.path().join("build/dev/dew.toml"));
+
+        // A profile moves both configs, and the old ones are pruned
+        let ci = manifest.clone().with_profile("ci").unwrap();
+        let results = generate_configs(&ci, &provider, dir.path(), &options).unwrap();
+        assert!(dir.path().join("target/ci/dew.toml").exists());
+        assert!(dir.path().join("target/lotus.toml").exists());
+        assert!(!dir.path().join("build").exists());
+        let pruned = results
+            .iter()
+            .filter(|r| matches!(r, GenerateResult::Pruned { .. }))
+            .count();
+        assert_eq!(pruned, 2);
+
+        // Under the same profile, clean and pull agree on the new paths
+        let stale = crate::clean::CleanOptions {
+            dry_run: true,
+            stale_only: true,
+            ..Default::default()
+        };
+        let cleaned = crate::clean::clean_configs(&ci, &provider, dir.path(), &stale).unwrap();
+        assert!(cleaned.is_empty(), "{cleaned:?}");
+        let pulled =
+            crate::pull::pull_configs(&["dew".to_string()], Some(&ci), &provider, dir.path())
+                .unwrap();
+        assert_eq!(pulled[0].path, dir.path().join("target/ci/dew.toml"));
+
+        let broken = Manifest::parse(
+            r#"
+            [project]
+            name = "test"
+
+            [dew]
+            output = "./out"
+
+            [dew._myenv]
+            config_path = ".dew/{{nope}}.toml"
+        "#,
+            Path::new("."),
+        )
+        .unwrap();
+        let err = generate_configs(&broken, &provider, dir.path(), &options).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("config_path '.dew/{{nope}}.toml' for 'dew' is invalid"),
+            "{err}"
+        );
+    }
 }
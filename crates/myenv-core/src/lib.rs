@@ -3,23 +3,41 @@
 //! Nursery is a configuration manager. It generates per-tool config files
 //! from a central `nursery.toml` manifest.
 
+mod cache;
+mod clean;
 mod config;
+mod diff;
 mod ecosystem;
+mod expand;
 mod generate;
 mod lockfile;
 mod manifest;
+mod merge;
 mod pull;
 mod repology;
 mod schema;
+mod state;
+mod sync;
 
+pub use cache::CachingSchemaProvider;
+pub use clean::{CleanOptions, CleanResult, clean_configs};
 pub use config::{ToolSource, ToolsConfig, UserConfig};
+pub use diff::{DEFAULT_CONTEXT, DiffLine, Hunk, diff_lines};
 pub use ecosystem::{Ecosystem, detect_ecosystems, detect_primary_ecosystem, is_installed};
 pub use generate::{
-    ConfigPreview, GenerateError, GenerateResult, GeneratedConfig, generate_configs,
-    preview_configs,
+    ConfigPreview, GenerateError, GenerateOptions, GenerateResult, GeneratedConfig, PreviewResult,
+    ToolValidation, ValidationIssue, ValidationOutcome, generate_configs, preview_configs,
+    validate_configs,
 };
 pub use lockfile::{LockedPackage, LockedTool, Lockfile, LockfileError};
-pub use manifest::{Manifest, ManifestError, Project, ToolDep};
+pub use manifest::{
+    ConfigTarget, Manifest, ManifestError, Project, Settings, ToolDep, ToolSettings,
+};
 pub use pull::{PullError, PulledConfig, merge_to_manifest, pull_configs};
 pub use repology::{PackageInfo, RepologyClient, RepologyError, ToolInfo};
-pub use schema::{CliSchemaProvider, ConfigFormat, SchemaError, SchemaProvider, ToolSchema};
+pub use schema::{
+    ChainSchemaProvider, CliSchemaProvider, ConfigFormat, DEFAULT_SCHEMA_TIMEOUT,
+    FileSchemaProvider, SchemaError, SchemaProvider, SchemaSource, ToolSchema,
+};
+pub use state::{GeneratedFile, GenerationState, StateError};
+pub use sync::{SyncConflict, SyncError, SyncReport, SyncResult, ToolSync, sync_configs};
@@ -1,9 +1,10 @@
 //! Manifest parsing for `nursery.toml`.
 
 use crate::config::ToolSource;
+use crate::schema::SchemaSource;
 use serde::Deserialize;
 use std::collections::BTreeMap;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 /// A parsed manifest.
 #[derive(Debug, Clone)]
@@ -22,8 +23,18 @@ pub struct Manifest {
     pub tool_source: Option<ToolSource>,
     /// Ecosystems to include in lockfile (optional).
     pub ecosystems: Option<Vec<String>>,
+    /// myenv's own settings from the `[myenv]` section.
+    pub settings: Settings,
     /// Tool configurations (e.g., `[siphon]`, `[dew]`).
     pub tool_configs: BTreeMap<String, toml::Value>,
+    /// Named overlays from `[profile.<name>]` sections.
+    pub profiles: BTreeMap<String, toml::Table>,
+    /// Profile applied via [`Manifest::with_profile`], if any.
+    pub active_profile: Option<String>,
+    /// Files that were merged into this manifest (the manifest itself, then
+    /// every included file). Only the included files when parsed from a
+    /// string.
+    pub files: Vec<PathBuf>,
 }
 
 /// A tool dependency specification.
@@ -124,6 +135,102 @@ pub struct Project {
     pub version: Option<String>,
 }
 
+/// myenv's own settings from the `[myenv]` section.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    /// Environment variables that `{{env.NAME}}` may read. `"*"` allows any.
+    pub allow_env: Vec<String>,
+    /// Programs that `{{cmd: ...}}` may run. Empty disables commands.
+    pub allow_commands: Vec<String>,
+    /// Where tool schemas come from, tried in order.
+    pub schema_sources: Option<Vec<SchemaSource>>,
+    /// Project directory holding `<tool>.json` schema files, relative to the
+    /// manifest (default: `schemas`).
+    pub schema_dir: Option<PathBuf>,
+}
+
+impl Settings {
+    /// Whether `{{env.NAME}}` may read the named variable.
+    pub fn env_allowed(&self, name: &str) -> bool {
+        self.allow_env.iter().any(|n| n == "*" || n == name)
+    }
+
+    /// Whether `{{cmd: ...}}` may run the named program.
+    pub fn command_allowed(&self, program: &str) -> bool {
+        self.allow_commands.iter().any(|n| n == program)
+    }
+
+    /// Schema sources in the order to try them: the tool binary, then the
+    /// project's schema files, then the user's, unless configured.
+    pub fn schema_sources(&self) -> &[SchemaSource] {
+        self.schema_sources.as_deref().unwrap_or(&[
+            SchemaSource::Cli,
+            SchemaSource::Project,
+            SchemaSource::User,
+        ])
+    }
+
+    /// Project schema directory, relative to the manifest.
+    pub fn schema_dir(&self) -> &Path {
+        self.schema_dir.as_deref().unwrap_or(Path::new("schemas"))
+    }
+}
+
+/// Per-tool settings from a reserved `[<tool>._myenv]` sub-table.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ToolSettings {
+    /// Update only the keys myenv manages in an existing config file,
+    /// keeping comments and other keys, instead of replacing the file.
+    pub merge: bool,
+    /// Executable to run for the schema, if not the section name. Paths
+    /// with a directory are relative to the manifest.
+    pub bin: Option<PathBuf>,
+    /// Arguments that make the tool print its schema (default: `--schema`).
+    pub schema_args: Option<Vec<String>>,
+    /// Where to write the config, relative to the manifest, instead of the
+    /// schema's `config_path`.
+    pub config_path: Option<PathBuf>,
+}
+
+/// A config to generate: a tool section, or one of its instances.
+///
+/// Instances live under `[<tool>.instances.<name>]` and are identified as
+/// `<tool>.<name>`, e.g. `dew.sprites`. Each is the tool's section with the
+/// instance's table merged over it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigTarget {
+    /// Identifier used for selection, state and output.
+    pub id: String,
+    /// Tool whose schema describes the config.
+    pub tool: String,
+    /// Instance name, for tools with instances.
+    pub instance: Option<String>,
+}
+
+impl ConfigTarget {
+    /// Split an identifier into its tool and instance. Manifests reject
+    /// names containing `.`, so the first `.` is always the separator.
+    pub fn from_id(id: &str) -> Self {
+        let (tool, instance) = match id.split_once('.') {
+            Some((tool, instance)) => (tool, Some(instance.to_string())),
+            None => (id, None),
+        };
+        Self {
+            id: id.to_string(),
+            tool: tool.to_string(),
+            instance,
+        }
+    }
+
+    /// Whether `name` selects this target: its own identifier, or its tool
+    /// (which selects every instance).
+    pub fn matches(&self, name: &str) -> bool {
+        self.id == name || self.tool == name
+    }
+}
+
 /// Errors that can occur when loading a manifest.
 #[derive(Debug, thiserror::Error)]
 pub enum ManifestError {
@@ -133,24 +240,383 @@ pub enum ManifestError {
     Parse(#[from] toml::de::Error),
     #[error("missing required [project] section")]
     MissingProject,
+    #[error("'include' must be an array of file paths")]
+    InvalidInclude,
+    #[error("include cycle: {}", format_chain(.0))]
+    IncludeCycle(Vec<PathBuf>),
+    #[error("[profile] must contain only tables, e.g. [profile.ci]")]
+    InvalidProfiles,
+    #[error("unknown profile '{0}'")]
+    UnknownProfile(String),
+    #[error("profile '{profile}' cannot override [{section}]")]
+    ProfileSection { profile: String, section: String },
+    #[error("profile '{profile}' section [{section}] must be a table")]
+    ProfileValue { profile: String, section: String },
+    #[error("[{0}.{key}] must contain only tables, e.g. [{0}.{key}.main]", key = INSTANCES_KEY)]
+    InvalidInstances(String),
+    #[error("invalid tool or instance name '{0}': names cannot contain '.'")]
+    InvalidName(String),
+    #[error("invalid [{tool}.{}]: {source}", TOOL_SETTINGS_KEY)]
+    ToolSettings {
+        tool: String,
+        source: toml::de::Error,
+    },
+    #[error("{}: {source}", path.display())]
+    InFile {
+        path: PathBuf,
+        source: Box<ManifestError>,
+    },
+}
+
+impl ManifestError {
+    /// Attribute an error to the file it came from.
+    fn in_file(path: &Path, source: impl Into<ManifestError>) -> Self {
+        Self::InFile {
+            path: path.to_path_buf(),
+            source: Box::new(source.into()),
+        }
+    }
+}
+
+fn format_chain(chain: &[PathBuf]) -> String {
+    chain
+        .iter()
+        .map(|p| p.display().to_string())
+        .collect::<Vec<_>>()
+        .join(" -> ")
+}
+
+/// Top-level key listing manifests to merge in before this one.
+const INCLUDE_KEY: &str = "include";
+
+/// Top-level key holding named overlays.
+const PROFILE_KEY: &str = "profile";
+
+/// Top-level key holding myenv's own settings.
+const SETTINGS_KEY: &str = "myenv";
+
+/// Sub-table of a tool section holding myenv's settings for that tool.
+/// It is never written to the tool's config.
+pub const TOOL_SETTINGS_KEY: &str = "_myenv";
+
+/// Sub-table of a tool section holding named instances of the tool.
+pub const INSTANCES_KEY: &str = "instances";
+
+/// Sections that are not tool configs.
+const RESERVED_SECTIONS: &[&str] = &[
+    "project",
+    SETTINGS_KEY,
+    "variables",
+    "tools",
+    "dev-tools",
+    "build-deps",
+    INCLUDE_KEY,
+    PROFILE_KEY,
+];
+
+/// Every config target, in manifest order. Invalid `instances` tables are
+/// rejected when the manifest is loaded.
+fn config_targets(tool_configs: &BTreeMap<String, toml::Value>) -> Vec<ConfigTarget> {
+    let mut targets = Vec::new();
+    for (tool, config) in tool_configs {
+        match config.get(INSTANCES_KEY).and_then(|v| v.as_table()) {
+            Some(instances) => targets.extend(
+                instances
+                    .keys()
+                    .map(|instance| ConfigTarget::from_id(&format!("{tool}.{instance}"))),
+            ),
+            None => targets.push(ConfigTarget::from_id(tool)),
+        }
+    }
+    targets
+}
+
+/// A target's section: the tool's own section, or for an instance, the
+/// tool's section without `instances` with the instance's table merged over.
+fn target_config(tool_configs: &BTreeMap<String, toml::Value>, id: &str) -> Option<toml::Value> {
+    let target = ConfigTarget::from_id(id);
+    let config = tool_configs.get(&target.tool)?;
+    let Some(instance) = &target.instance else {
+        return Some(config.clone());
+    };
+
+    let overlay = config
+        .get(INSTANCES_KEY)?
+        .get(instance)?
+        .as_table()?
+        .clone();
+    let mut merged = config.as_table()?.clone();
+    merged.remove(INSTANCES_KEY);
+    deep_merge(&mut merged, overlay);
+    Some(toml::Value::Table(merged))
+}
+
+fn tool_settings(
+    tool_configs: &BTreeMap<String, toml::Value>,
+    id: &str,
+) -> Result<ToolSettings, ManifestError> {
+    match target_config(tool_configs, id)
+        .as_ref()
+        .and_then(|c| c.get(TOOL_SETTINGS_KEY))
+    {
+        Some(value) => value
+            .clone()
+            .try_into()
+            .map_err(|source| ManifestError::ToolSettings {
+                tool: id.to_string(),
+                source,
+            }),
+        None => Ok(ToolSettings::default()),
+    }
+}
+
+/// Check a tool's `instances` table and the `_myenv` settings of each of
+/// its targets.
+fn check_tool(
+    tool_configs: &BTreeMap<String, toml::Value>,
+    tool: &str,
+) -> Result<(), ManifestError> {
+    // Target ids join tool and instance with '.', so neither may contain one
+    if tool.contains('.') {
+        return Err(ManifestError::InvalidName(tool.to_string()));
+    }
+    if let Some(instances) = tool_configs.get(tool).and_then(|c| c.get(INSTANCES_KEY)) {
+        let Some(instances) = instances
+            .as_table()
+            .filter(|t| t.values().all(toml::Value::is_table))
+        else {
+            return Err(ManifestError::InvalidInstances(tool.to_string()));
+        };
+        if let Some(name) = instances.keys().find(|name| name.contains('.')) {
+            return Err(ManifestError::InvalidName(format!("{tool}.{name}")));
+        }
+    }
+    config_targets(tool_configs)
+        .iter()
+        .filter(|target| target.tool == tool)
+        .try_for_each(|target| tool_settings(tool_configs, &target.id).map(drop))
+}
+
+/// A raw manifest table with included files merged in.
+#[derive(Debug, Default)]
+struct Layer {
+    table: toml::Table,
+    /// Each file's own table, to find where a value came from.
+    sources: Sources,
+    /// Every file that was loaded, in load order.
+    files: Vec<PathBuf>,
+}
+
+impl Layer {
+    /// Merge `other` on top of this layer.
+    fn merge(&mut self, other: Layer) {
+        deep_merge(&mut self.table, other.table);
+        self.sources.0.extend(other.sources.0);
+        self.files.extend(other.files);
+    }
+}
+
+/// The tables merged into a manifest, lowest precedence first, with the file
+/// each came from (`None` when parsed from a string).
+#[derive(Debug, Default)]
+struct Sources(Vec<(Option<PathBuf>, toml::Table)>);
+
+impl Sources {
+    /// The file the value at a key path came from: the last one to set the
+    /// longest part of the path that any of them sets.
+    fn origin(&self, path: &[&str]) -> Option<&Path> {
+        let contains = |table: &toml::Table, path: &[&str]| {
+            let mut value = table.get(path[0]);
+            for key in &path[1..] {
+                value = value.and_then(|v| v.get(key));
+            }
+            value.is_some()
+        };
+        (1..=path.len())
+            .rev()
+            .find_map(|len| {
+                self.0
+                    .iter()
+                    .rev()
+                    .find(|(_, table)| contains(table, &path[..len]))
+            })
+            .and_then(|(file, _)| file.as_deref())
+    }
+
+    /// Attribute an error about the value at a key path to its file.
+    fn attribute(&self, path: &[&str], e: impl Into<ManifestError>) -> ManifestError {
+        match self.origin(path) {
+            Some(file) => ManifestError::in_file(file, e),
+            None => e.into(),
+        }
+    }
+}
+
+/// The first key of a table that `T` rejects on its own, for types whose
+/// fields all have defaults.
+fn invalid_key<T: serde::de::DeserializeOwned>(value: &toml::Value) -> Option<&str> {
+    let (key, _) = value.as_table()?.iter().find(|(key, value)| {
+        let single = toml::Table::from_iter([((*key).clone(), (*value).clone())]);
+        toml::Value::Table(single).try_into::<T>().is_err()
+    })?;
+    Some(key)
+}
+
+/// The key path a [`check_tool`] error is about.
+fn error_path<'a>(
+    tool_configs: &'a BTreeMap<String, toml::Value>,
+    tool: &'a str,
+    error: &'a ManifestError,
+) -> Vec<&'a str> {
+    match error {
+        ManifestError::InvalidInstances(_) => vec![tool, INSTANCES_KEY],
+        ManifestError::InvalidName(name) if name != tool => {
+            let instance = &name[tool.len() + 1..];
+            vec![tool, INSTANCES_KEY, instance]
+        }
+        ManifestError::ToolSettings { tool: id, .. } => {
+            let mut candidates = vec![vec![tool, TOOL_SETTINGS_KEY]];
+            if let Some((_, instance)) = id.split_once('.') {
+                candidates.push(vec![tool, INSTANCES_KEY, instance, TOOL_SETTINGS_KEY]);
+            }
+            let lookup = |path: &[&str]| {
+                path[1..]
+                    .iter()
+                    .try_fold(tool_configs.get(path[0])?, |value, key| value.get(key))
+            };
+            // The tool's settings are merged under the instance's, so check
+            // them first
+            for path in &candidates {
+                let Some(settings) = lookup(path) else {
+                    continue;
+                };
+                if !settings.is_table() {
+                    return path.clone();
+                }
+                if let Some(key) = invalid_key::<ToolSettings>(settings) {
+                    return [path.as_slice(), &[key]].concat();
+                }
+            }
+            candidates.pop().unwrap_or_default()
+        }
+        _ => vec![tool],
+    }
+}
+
+/// Deep-merge `overlay` into `base`.
+///
+/// Tables are merged key by key; any other value (including arrays) in
+/// `overlay` replaces the value in `base`.
+pub(crate) fn deep_merge(base: &mut toml::Table, overlay: toml::Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(existing)), toml::Value::Table(incoming)) => {
+                deep_merge(existing, incoming);
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Load a manifest file and everything it includes.
+fn load_layer(path: &Path, stack: &mut Vec<PathBuf>) -> Result<Layer, ManifestError> {
+    let canonical = path
+        .canonicalize()
+        .map_err(|e| ManifestError::in_file(path, e))?;
+    if stack.contains(&canonical) {
+        let mut chain = stack.clone();
+        chain.push(canonical);
+        return Err(ManifestError::IncludeCycle(chain));
+    }
+
+    let contents = std::fs::read_to_string(path).map_err(|e| ManifestError::in_file(path, e))?;
+    let table: toml::Table =
+        toml::from_str(&contents).map_err(|e| ManifestError::in_file(path, e))?;
+
+    stack.push(canonical);
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let layer = resolve_includes(table, dir, Some(path), stack);
+    stack.pop();
+    layer
+}
+
+/// Merge a manifest table on top of the files it includes.
+///
+/// Includes are applied in order, so later includes override earlier ones,
+/// and the including manifest overrides all of them.
+fn resolve_includes(
+    mut table: toml::Table,
+    dir: &Path,
+    file: Option<&Path>,
+    stack: &mut Vec<PathBuf>,
+) -> Result<Layer, ManifestError> {
+    let attribute = |e: ManifestError| match file {
+        Some(path) => ManifestError::in_file(path, e),
+        None => e,
+    };
+
+    let includes: Vec<String> = match table.remove(INCLUDE_KEY) {
+        None => Vec::new(),
+        Some(toml::Value::Array(arr)) => arr
+            .iter()
+            .map(|v| v.as_str().map(String::from))
+            .collect::<Option<_>>()
+            .ok_or_else(|| attribute(ManifestError::InvalidInclude))?,
+        Some(_) => return Err(attribute(ManifestError::InvalidInclude)),
+    };
+
+    let mut layer = Layer::default();
+    for include in includes {
+        layer.merge(load_layer(&dir.join(include), stack)?);
+    }
+
+    let own = Layer {
+        sources: Sources(vec![(file.map(Path::to_path_buf), table.clone())]),
+        files: file.map(|f| vec![f.to_path_buf()]).unwrap_or_default(),
+        table,
+    };
+    // The including manifest is listed before the files it pulls in.
+    let included = std::mem::take(&mut layer.files);
+    layer.merge(own);
+    layer.files.extend(included);
+
+    Ok(layer)
 }
 
 impl Manifest {
-    /// Load a manifest from a file path.
+    /// Load a manifest from a file path, resolving `include`s relative to it.
     pub fn from_path(path: impl AsRef<Path>) -> Result<Self, ManifestError> {
-        let contents = std::fs::read_to_string(path)?;
-        Self::parse(&contents)
+        let layer = load_layer(path.as_ref(), &mut Vec::new())?;
+        Self::from_layer(layer)
     }
 
     /// Parse a manifest from a TOML string.
-    pub fn parse(s: &str) -> Result<Self, ManifestError> {
-        let mut table: toml::Table = toml::from_str(s)?;
+    ///
+    /// Any `include`s are resolved relative to `base_dir`, normally the
+    /// directory the manifest lives in.
+    pub fn parse(s: &str, base_dir: &Path) -> Result<Self, ManifestError> {
+        let table: toml::Table = toml::from_str(s)?;
+        let layer = resolve_includes(table, base_dir, None, &mut Vec::new())?;
+        Self::from_layer(layer)
+    }
+
+    /// Build a manifest from a fully merged table.
+    fn from_layer(layer: Layer) -> Result<Self, ManifestError> {
+        let Layer {
+            mut table,
+            sources,
+            files,
+        } = layer;
 
         // Extract and parse the project section
         let project_value = table
             .remove("project")
             .ok_or(ManifestError::MissingProject)?;
-        let project: Project = project_value.try_into()?;
+        let project: Project = project_value
+            .try_into()
+            .map_err(|e: toml::de::Error| sources.attribute(&["project"], e))?;
 
         // Extract variables section (optional)
         let variables = table
@@ -159,6 +625,16 @@ impl Manifest {
             .map(|t| t.into_iter().collect())
             .unwrap_or_default();
 
+        // Extract myenv settings (optional)
+        let settings = match table.remove(SETTINGS_KEY) {
+            Some(value) => value.clone().try_into().map_err(|e: toml::de::Error| {
+                let mut path = vec![SETTINGS_KEY];
+                path.extend(invalid_key::<Settings>(&value));
+                sources.attribute(&path, e)
+            })?,
+            None => Settings::default(),
+        };
+
         // Extract tools section (dependencies, optional)
         let (tool_deps, tool_source, ecosystems) = if let Some(tools_value) = table.remove("tools")
         {
@@ -198,8 +674,31 @@ impl Manifest {
         // Extract build-deps section (optional)
         let build_deps = parse_deps_section(table.remove("build-deps"));
 
+        // Extract profile overlays (optional)
+        let profiles = match table.remove(PROFILE_KEY) {
+            None => BTreeMap::new(),
+            Some(toml::Value::Table(t)) => t
+                .into_iter()
+                .map(|(name, overlay)| match overlay {
+                    toml::Value::Table(overlay) => Ok((name, overlay)),
+                    _ => Err(ManifestError::InvalidProfiles),
+                })
+                .collect::<Result<_, _>>()
+                .map_err(|e| sources.attribute(&[PROFILE_KEY], e))?,
+            Some(_) => return Err(ManifestError::InvalidProfiles),
+        };
+
         // Everything else is a tool config section
-        let tool_configs = table.into_iter().collect();
+        let tool_configs: BTreeMap<_, _> = table.into_iter().collect();
+        for tool in tool_configs.keys() {
+            check_tool(&tool_configs, tool).map_err(|e| {
+                let path = error_path(&tool_configs, tool, &e);
+                match sources.origin(&path).map(Path::to_path_buf) {
+                    Some(file) => ManifestError::in_file(&file, e),
+                    None => e,
+                }
+            })?;
+        }
 
         Ok(Self {
             project,
@@ -209,10 +708,84 @@ impl Manifest {
             build_deps,
             tool_source,
             ecosystems,
+            settings,
             tool_configs,
+            profiles,
+            active_profile: None,
+            files,
         })
     }
 
+    /// Apply a named profile overlay.
+    ///
+    /// The profile's `[profile.<name>.variables]` and tool sections are
+    /// deep-merged over the base manifest using the same rules as `include`.
+    pub fn with_profile(mut self, name: &str) -> Result<Self, ManifestError> {
+        let overlay = self
+            .profiles
+            .get(name)
+            .cloned()
+            .ok_or_else(|| ManifestError::UnknownProfile(name.to_string()))?;
+
+        for (section, value) in overlay {
+            if section != "variables" && RESERVED_SECTIONS.contains(&section.as_str()) {
+                return Err(ManifestError::ProfileSection {
+                    profile: name.to_string(),
+                    section,
+                });
+            }
+            let toml::Value::Table(value) = value else {
+                return Err(ManifestError::ProfileValue {
+                    profile: name.to_string(),
+                    section,
+                });
+            };
+
+            if section == "variables" {
+                let mut variables: toml::Table =
+                    std::mem::take(&mut self.variables).into_iter().collect();
+                deep_merge(&mut variables, value);
+                self.variables = variables.into_iter().collect();
+            } else {
+                match self.tool_configs.get_mut(&section) {
+                    Some(toml::Value::Table(existing)) => deep_merge(existing, value),
+                    _ => {
+                        self.tool_configs.insert(section, toml::Value::Table(value));
+                    }
+                }
+            }
+        }
+
+        for tool in self.tool_configs.keys() {
+            check_tool(&self.tool_configs, tool)?;
+        }
+
+        self.active_profile = Some(name.to_string());
+        Ok(self)
+    }
+
+    /// Every config to generate, in manifest order: each tool section, or
+    /// each of its instances if it has any.
+    pub fn targets(&self) -> Vec<ConfigTarget> {
+        config_targets(&self.tool_configs)
+    }
+
+    /// Whether a target with this identifier exists.
+    pub fn has_target(&self, id: &str) -> bool {
+        self.targets().iter().any(|target| target.id == id)
+    }
+
+    /// A target's section, with an instance's table merged over its tool's.
+    pub fn target_config(&self, id: &str) -> Option<toml::Value> {
+        target_config(&self.tool_configs, id)
+    }
+
+    /// Settings from a target's `_myenv` sub-table (defaults if absent).
+    /// An instance's settings are merged over its tool's.
+    pub fn tool_settings(&self, id: &str) -> Result<ToolSettings, ManifestError> {
+        tool_settings(&self.tool_configs, id)
+    }
+
     /// Get a variable value as a string.
     pub fn get_variable(&self, name: &str) -> Option<String> {
         self.variables.get(name).and_then(|v| match v {
@@ -237,7 +810,7 @@ mod tests {
             version = "0.1.0"
         "#;
 
-        let manifest = Manifest::parse(toml).unwrap();
+        let manifest = Manifest::parse(toml, Path::new(".")).unwrap();
         assert_eq!(manifest.project.name, "test");
         assert_eq!(manifest.project.version, Some("0.1.0".to_string()));
         assert!(manifest.variables.is_empty());
@@ -258,7 +831,7 @@ mod tests {
             count = 42
         "#;
 
-        let manifest = Manifest::parse(toml).unwrap();
+        let manifest = Manifest::parse(toml, Path::new(".")).unwrap();
         assert_eq!(
             manifest.get_variable("assets"),
             Some("./assets".to_string())
@@ -282,7 +855,7 @@ mod tests {
             pipeline = "assets.dew"
         "#;
 
-        let manifest = Manifest::parse(toml).unwrap();
+        let manifest = Manifest::parse(toml, Path::new(".")).unwrap();
         assert_eq!(manifest.tool_configs.len(), 2);
         assert!(manifest.tool_configs.contains_key("siphon"));
         assert!(manifest.tool_configs.contains_key("dew"));
@@ -301,7 +874,7 @@ mod tests {
             jq = { version = "=1.7", optional = true }
         "#;
 
-        let manifest = Manifest::parse(toml).unwrap();
+        let manifest = Manifest::parse(toml, Path::new(".")).unwrap();
         assert_eq!(manifest.tool_deps.len(), 3);
 
         let rg = &manifest.tool_deps["ripgrep"];
@@ -328,7 +901,7 @@ mod tests {
             ripgrep = ">=14"
         "#;
 
-        let manifest = Manifest::parse(toml).unwrap();
+        let manifest = Manifest::parse(toml, Path::new(".")).unwrap();
         assert_eq!(
             manifest.ecosystems,
             Some(vec!["pacman".to_string(), "nix".to_string()])
@@ -343,7 +916,7 @@ mod tests {
             source = "./game.exe"
         "#;
 
-        let err = Manifest::parse(toml).unwrap_err();
+        let err = Manifest::parse(toml, Path::new(".")).unwrap_err();
         assert!(matches!(err, ManifestError::MissingProject));
     }
 
@@ -362,7 +935,7 @@ mod tests {
             tokei = ">=12"
         "#;
 
-        let manifest = Manifest::parse(toml).unwrap();
+        let manifest = Manifest::parse(toml, Path::new(".")).unwrap();
         assert_eq!(manifest.tool_deps.len(), 1);
         assert_eq!(manifest.dev_tool_deps.len(), 2);
         assert!(manifest.dev_tool_deps.contains_key("fd-find"));
@@ -381,7 +954,7 @@ mod tests {
             cmake = ">=3.20"
         "#;
 
-        let manifest = Manifest::parse(toml).unwrap();
+        let manifest = Manifest::parse(toml, Path::new(".")).unwrap();
         assert_eq!(manifest.build_deps.len(), 2);
 
         let openssl = &manifest.build_deps["openssl"];
@@ -396,6 +969,232 @@ mod tests {
         assert!(cmake.overrides.is_empty());
     }
 
+    fn write(dir: &Path, name: &str, contents: &str) -> PathBuf {
+        let path = dir.join(name);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn include_deep_merges_sections() {
+        let dir = tempfile::tempdir().unwrap();
+        write(
+            dir.path(),
+            "shared/base.toml",
+            r#"
+            [project]
+            name = "base"
+            version = "0.1.0"
+
+            [variables]
+            assets = "./assets"
+            out = "./out"
+
+            [dew]
+            pipeline = "base.dew"
+            tags = ["a", "b"]
+        "#,
+        );
+        let path = write(
+            dir.path(),
+            "game/nursery.toml",
+            r#"
+            include = ["../shared/base.toml"]
+
+            [project]
+            name = "game"
+
+            [variables]
+            out = "./build"
+
+            [dew]
+            tags = ["c"]
+        "#,
+        );
+
+        let manifest = Manifest::from_path(&path).unwrap();
+        assert_eq!(manifest.project.name, "game");
+        assert_eq!(manifest.project.version, Some("0.1.0".to_string()));
+        assert_eq!(
+            manifest.get_variable("assets"),
+            Some("./assets".to_string())
+        );
+        assert_eq!(manifest.get_variable("out"), Some("./build".to_string()));
+
+        let dew = manifest.tool_configs["dew"].as_table().unwrap();
+        assert_eq!(dew["pipeline"].as_str(), Some("base.dew"));
+        assert_eq!(dew["tags"].as_array().unwrap().len(), 1);
+        assert!(!manifest.tool_configs.contains_key("include"));
+        assert_eq!(manifest.files.len(), 2);
+    }
+
+    #[test]
+    fn parse_resolves_includes_against_base_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        write(
+            dir.path(),
+            "base.toml",
+            "[variables]\nassets = \"./assets\"",
+        );
+
+        let manifest = Manifest::parse(
+            "include = [\"base.toml\"]\n[project]\nname = \"test\"",
+            dir.path(),
+        )
+        .unwrap();
+        assert_eq!(
+            manifest.get_variable("assets"),
+            Some("./assets".to_string())
+        );
+    }
+
+    #[test]
+    fn include_cycle_is_detected() {
+        let dir = tempfile::tempdir().unwrap();
+        write(dir.path(), "a.toml", "include = [\"b.toml\"]");
+        write(dir.path(), "b.toml", "include = [\"a.toml\"]");
+        let path = write(
+            dir.path(),
+            "nursery.toml",
+            "include = [\"a.toml\"]\n[project]\nname = \"test\"",
+        );
+
+        let err = Manifest::from_path(&path).unwrap_err();
+        assert!(matches!(err, ManifestError::IncludeCycle(ref chain) if chain.len() == 4));
+    }
+
+    #[test]
+    fn include_errors_name_the_file() {
+        let dir = tempfile::tempdir().unwrap();
+        write(dir.path(), "base.toml", "[project]\nname = 42");
+        let path = write(dir.path(), "nursery.toml", "include = [\"base.toml\"]");
+
+        let err = Manifest::from_path(&path).unwrap_err();
+        match err {
+            ManifestError::InFile { path, .. } => assert!(path.ends_with("base.toml")),
+            other => panic!("expected error attributed to base.toml, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn include_errors_name_the_file_that_set_the_value() {
+        let dir = tempfile::tempdir().unwrap();
+        write(
+            dir.path(),
+            "base.toml",
+            "[dew._myenv]\nmerge = \"yes\"\n\n[myenv]\nallow_env = \"HOME\"",
+        );
+        let path = write(
+            dir.path(),
+            "nursery.toml",
+            r#"
+            include = ["base.toml"]
+
+            [project]
+            name = "test"
+
+            [myenv]
+            allow_commands = ["git"]
+
+            [dew]
+            threads = 4
+
+            [dew._myenv]
+            bin = "./dew"
+        "#,
+        );
+
+        let blamed = |err: ManifestError| match err {
+            ManifestError::InFile { path, source } => (path, source),
+            other => panic!("expected an error attributed to a file, got {other:?}"),
+        };
+        let (file, source) = blamed(Manifest::from_path(&path).unwrap_err());
+        assert!(file.ends_with("base.toml"));
+        assert!(matches!(*source, ManifestError::Parse(_)));
+
+        // With [myenv] fixed, the bad tool setting is found in base.toml too
+        write(dir.path(), "base.toml", "[dew._myenv]\nmerge = \"yes\"");
+        let (file, source) = blamed(Manifest::from_path(&path).unwrap_err());
+        assert!(file.ends_with("base.toml"));
+        assert!(matches!(*source, ManifestError::ToolSettings { .. }));
+
+        // A bad value set by the including manifest is attributed to it
+        write(dir.path(), "base.toml", "[dew]\noutput = \"./out\"");
+        let fixed = std::fs::read_to_string(&path)
+            .unwrap()
+            .replace("bin = \"./dew\"", "merge = 1");
+        std::fs::write(&path, fixed).unwrap();
+        let (file, _) = blamed(Manifest::from_path(&path).unwrap_err());
+        assert!(file.ends_with("nursery.toml"));
+    }
+
+    #[test]
+    fn profile_overrides_variables_and_tools() {
+        let toml = r#"
+            [project]
+            name = "test"
+
+            [variables]
+            out = "./out"
+            debug = true
+
+            [dew]
+            pipeline = "main.dew"
+            threads = 4
+
+            [profile.ci.variables]
+            debug = false
+
+            [profile.ci.dew]
+            threads = 1
+        "#;
+
+        let manifest = Manifest::parse(toml, Path::new(".")).unwrap();
+        assert!(!manifest.tool_configs.contains_key("profile"));
+        assert!(manifest.profiles.contains_key("ci"));
+
+        let ci = manifest.with_profile("ci").unwrap();
+        assert_eq!(ci.active_profile.as_deref(), Some("ci"));
+        assert_eq!(ci.get_variable("out"), Some("./out".to_string()));
+        assert_eq!(ci.get_variable("debug"), Some("false".to_string()));
+
+        let dew = ci.tool_configs["dew"].as_table().unwrap();
+        assert_eq!(dew["pipeline"].as_str(), Some("main.dew"));
+        assert_eq!(dew["threads"].as_integer(), Some(1));
+    }
+
+    #[test]
+    fn unknown_profile() {
+        let toml = r#"
+            [project]
+            name = "test"
+        "#;
+
+        let err = Manifest::parse(toml, Path::new("."))
+            .unwrap()
+            .with_profile("release")
+            .unwrap_err();
+        assert!(matches!(err, ManifestError::UnknownProfile(name) if name == "release"));
+    }
+
+    #[test]
+    fn profile_cannot_override_project() {
+        let toml = r#"
+            [project]
+            name = "test"
+
+            [profile.ci.project]
+            name = "other"
+        "#;
+
+        let err = Manifest::parse(toml, Path::new("."))
+            .unwrap()
+            .with_profile("ci")
+            .unwrap_err();
+        assert!(matches!(err, ManifestError::ProfileSection { .. }));
+    }
+
     #[test]
     fn tool_dep_package_name() {
         let toml = r#"
@@ -407,7 +1206,7 @@ mod tests {
             openssl = { version = "*", apt = "libssl-dev", pacman = "openssl" }
         "#;
 
-        let manifest = Manifest::parse(toml).unwrap();
+        let manifest = Manifest::parse(toml, Path::new(".")).unwrap();
         let openssl = &manifest.build_deps["openssl"];
 
         // Uses override when present
@@ -417,4 +1216,143 @@ mod tests {
         // Falls back to default when no override
         assert_eq!(openssl.package_name("brew", "openssl"), "openssl");
     }
+
+    #[test]
+    fn parse_tool_settings() {
+        let manifest = Manifest::parse(
+            r#"
+            [project]
+            name = "test"
+
+            [siphon]
+            source = "./dump"
+
+            [siphon._myenv]
+            bin = "./target/release/siphon"
+            schema_args = ["schema", "--json"]
+            config_path = "build/siphon.toml"
+        "#,
+            Path::new("."),
+        )
+        .unwrap();
+
+        let settings = manifest.tool_settings("siphon").unwrap();
+        assert_eq!(settings.bin, Some(PathBuf::from("./target/release/siphon")));
+        assert_eq!(settings.schema_args.unwrap(), ["schema", "--json"]);
+        assert_eq!(
+            settings.config_path,
+            Some(PathBuf::from("build/siphon.toml"))
+        );
+        assert!(!settings.merge);
+
+        let err = Manifest::parse(
+            r#"
+            [project]
+            name = "test"
+
+            [siphon._myenv]
+            binary = "siphon-dev"
+        "#,
+            Path::new("."),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ManifestError::ToolSettings { tool, .. } if tool == "siphon"));
+    }
+
+    #[test]
+    fn parse_tool_instances() {
+        let manifest = Manifest::parse(
+            r#"
+            [project]
+            name = "test"
+
+            [dew]
+            threads = 4
+
+            [dew._myenv]
+            config_path = ".dew/{{instance}}.toml"
+
+            [dew.instances.sprites]
+            input = "./sprites"
+
+            [dew.instances.audio]
+            input = "./audio"
+            threads = 1
+
+            [dew.instances.audio._myenv]
+            merge = true
+
+            [lotus]
+            port = 8080
+        "#,
+            Path::new("."),
+        )
+        .unwrap();
+
+        let ids: Vec<_> = manifest.targets().into_iter().map(|t| t.id).collect();
+        assert_eq!(ids, ["dew.audio", "dew.sprites", "lotus"]);
+        assert!(manifest.has_target("dew.sprites"));
+        assert!(!manifest.has_target("dew"));
+
+        let audio = manifest.target_config("dew.audio").unwrap();
+        assert_eq!(audio["input"].as_str(), Some("./audio"));
+        assert_eq!(audio["threads"].as_integer(), Some(1));
+        assert!(audio.get(INSTANCES_KEY).is_none());
+        let sprites = manifest.target_config("dew.sprites").unwrap();
+        assert_eq!(sprites["threads"].as_integer(), Some(4));
+
+        // Instance settings are merged over the tool's
+        let settings = manifest.tool_settings("dew.audio").unwrap();
+        assert!(settings.merge);
+        assert_eq!(
+            settings.config_path,
+            Some(PathBuf::from(".dew/{{instance}}.toml"))
+        );
+        assert!(!manifest.tool_settings("dew.sprites").unwrap().merge);
+
+        let target = ConfigTarget::from_id("dew.sprites");
+        assert_eq!(target.tool, "dew");
+        assert_eq!(target.instance.as_deref(), Some("sprites"));
+        assert!(target.matches("dew") && target.matches("dew.sprites"));
+        assert!(!target.matches("dew.audio"));
+
+        let err = Manifest::parse(
+            r#"
+            [project]
+            name = "test"
+
+            [dew]
+            instances = ["sprites"]
+        "#,
+            Path::new("."),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ManifestError::InvalidInstances(tool) if tool == "dew"));
+
+        let err = Manifest::parse(
+            r#"
+            [project]
+            name = "test"
+
+            [dew.instances."v1.2"]
+            input = "./old"
+        "#,
+            Path::new("."),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ManifestError::InvalidName(name) if name == "dew.v1.2"));
+
+        let err = Manifest::parse(
+            r#"
+            [project]
+            name = "test"
+
+            ["dew.old"]
+            input = "./old"
+        "#,
+            Path::new("."),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ManifestError::InvalidName(name) if name == "dew.old"));
+    }
 }
@@ -0,0 +1,338 @@
+//! Merging generated values into existing config files.
+//!
+//! Used for tools with `merge = true` in their `_myenv` settings. Only keys
+//! present in the generated config are updated, and keys that were generated
+//! last time but have since left the manifest are removed; other keys are
+//! kept. TOML files keep their comments, formatting and key order. JSON and
+//! YAML are merged structurally, so comments and formatting are not
+//! preserved.
+
+use crate::generate::json_to_toml;
+use crate::manifest::TOOL_SETTINGS_KEY;
+use crate::schema::ConfigFormat;
+use serde::Deserialize;
+use toml_edit::{DocumentMut, TableLike};
+
+/// Merge a generated config into the contents of an existing file.
+///
+/// `previous` is the config generated last time; keys it has that `config`
+/// doesn't are removed from the file.
+pub(crate) fn merge_config(
+    existing: &str,
+    config: &serde_json::Value,
+    previous: Option<&serde_json::Value>,
+    format: ConfigFormat,
+) -> Result<String, String> {
+    let dropped = previous.map_or_else(Vec::new, |previous| dropped_keys(previous, config));
+    match format {
+        ConfigFormat::Toml => merge_toml(existing, config, &dropped),
+        ConfigFormat::Json => {
+            let mut target: serde_json::Value =
+                serde_json::from_str(existing).map_err(|e| e.to_string())?;
+            for path in &dropped {
+                remove_json(&mut target, path);
+            }
+            merge_json(&mut target, config);
+            serde_json::to_string_pretty(&target).map_err(|e| e.to_string())
+        }
+        ConfigFormat::Yaml => {
+            let mut target: serde_yaml::Value =
+                serde_yaml::from_str(existing).map_err(|e| e.to_string())?;
+            let source = serde_yaml::to_value(config).map_err(|e| e.to_string())?;
+            for path in &dropped {
+                remove_yaml(&mut target, path);
+            }
+            merge_yaml(&mut target, source);
+            serde_yaml::to_string(&target).map_err(|e| e.to_string())
+        }
+    }
+}
+
+fn merge_toml(
+    existing: &str,
+    config: &serde_json::Value,
+    dropped: &[Vec<&str>],
+) -> Result<String, String> {
+    let mut doc: DocumentMut = existing.parse().map_err(|e| format!("{e}"))?;
+    let generated =
+        toml_edit::ser::to_document(&json_to_toml(config)).map_err(|e| e.to_string())?;
+    for path in dropped {
+        remove_toml(doc.as_table_mut(), path);
+    }
+    merge_table(doc.as_table_mut(), generated.as_table(), false);
+    Ok(doc.to_string())
+}
+
+/// Key paths in `previous` that are missing from `config`.
+fn dropped_keys<'a>(
+    previous: &'a serde_json::Value,
+    config: &serde_json::Value,
+) -> Vec<Vec<&'a str>> {
+    let (Some(previous), Some(config)) = (previous.as_object(), config.as_object()) else {
+        return Vec::new();
+    };
+    let mut dropped = Vec::new();
+    for (key, value) in previous {
+        match config.get(key) {
+            None => dropped.push(vec![key.as_str()]),
+            Some(current) => {
+                for mut path in dropped_keys(value, current) {
+                    path.insert(0, key.as_str());
+                    dropped.push(path);
+                }
+            }
+        }
+    }
+    dropped
+}
+
+fn remove_toml(table: &mut dyn TableLike, path: &[&str]) {
+    match path {
+        [] => {}
+        [key] => {
+            table.remove(key);
+        }
+        [key, rest @ ..] => {
+            if let Some(table) = table.get_mut(key).and_then(|item| item.as_table_like_mut()) {
+                remove_toml(table, rest);
+            }
+        }
+    }
+}
+
+fn remove_json(value: &mut serde_json::Value, path: &[&str]) {
+    let Some(map) = value.as_object_mut() else {
+        return;
+    };
+    match path {
+        [] => {}
+        [key] => {
+            map.remove(*key);
+        }
+        [key, rest @ ..] => {
+            if let Some(value) = map.get_mut(*key) {
+                remove_json(value, rest);
+            }
+        }
+    }
+}
+
+fn remove_yaml(value: &mut serde_yaml::Value, path: &[&str]) {
+    let Some(map) = value.as_mapping_mut() else {
+        return;
+    };
+    match path {
+        [] => {}
+        [key] => {
+            map.remove(*key);
+        }
+        [key, rest @ ..] => {
+            if let Some(value) = map.get_mut(*key) {
+                remove_yaml(value, rest);
+            }
+        }
+    }
+}
+
+/// Copy keys from `source` into `target`, keeping the comments and
+/// formatting of values that already exist.
+///
+/// With `prune`, keys missing from `source` are removed from `target`,
+/// except for `_myenv` settings.
+pub(crate) fn merge_table(target: &mut dyn TableLike, source: &dyn TableLike, prune: bool) {
+    if prune {
+        let stale: Vec<_> = target
+            .iter()
+            .map(|(key, _)| key.to_string())
+            .filter(|key| key != TOOL_SETTINGS_KEY && !source.contains_key(key))
+            .collect();
+        for key in stale {
+            target.remove(&key);
+        }
+    }
+
+    for (key, item) in source.iter() {
+        match target.get_mut(key) {
+            Some(existing) if existing.is_table_like() && item.is_table_like() => {
+                if let (Some(existing), Some(item)) =
+                    (existing.as_table_like_mut(), item.as_table_like())
+                {
+                    merge_table(existing, item, prune);
+                }
+            }
+            Some(toml_edit::Item::Value(existing)) if item.is_value() => {
+                let Some(value) = item.as_value() else {
+                    continue;
+                };
+                // Leave equal values alone so their formatting survives
+                if !same_value(existing, value) {
+                    let decor = existing.decor().clone();
+                    *existing = value.clone();
+                    *existing.decor_mut() = decor;
+                }
+            }
+            _ => {
+                target.insert(key, item.clone());
+            }
+        }
+    }
+}
+
+/// Compare two TOML values ignoring formatting (`'a'` equals `"a"`).
+fn same_value(a: &toml_edit::Value, b: &toml_edit::Value) -> bool {
+    let parse = |v: &toml_edit::Value| {
+        let mut v = v.clone();
+        v.decor_mut().clear();
+        let de: toml_edit::de::ValueDeserializer = v.to_string().parse().ok()?;
+        toml::Value::deserialize(de).ok()
+    };
+    parse(a).is_some_and(|a| Some(a) == parse(b))
+}
+
+fn merge_json(target: &mut serde_json::Value, source: &serde_json::Value) {
+    match (target, source) {
+        (serde_json::Value::Object(target), serde_json::Value::Object(source)) => {
+            for (key, value) in source {
+                match target.get_mut(key) {
+                    Some(existing) => merge_json(existing, value),
+                    None => {
+                        target.insert(key.clone(), value.clone());
+                    }
+                }
+            }
+        }
+        (target, source) => *target = source.clone(),
+    }
+}
+
+fn merge_yaml(target: &mut serde_yaml::Value, source: serde_yaml::Value) {
+    match (target, source) {
+        (serde_yaml::Value::Mapping(target), serde_yaml::Value::Mapping(source)) => {
+            for (key, value) in source {
+                match target.get_mut(&key) {
+                    Some(existing) => merge_yaml(existing, value),
+                    None => {
+                        target.insert(key, value);
+                    }
+                }
+            }
+        }
+        (target, source) => *target = source,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn toml_merge_keeps_comments_and_unmanaged_keys() {
+        let existing = r#"# Hand-maintained settings
+[pipeline]
+# where assets come from
+input = './old'   # trailing note
+threads = 4       # not managed by myenv
+
+[extra]
+debug = true
+"#;
+        let config = serde_json::json!({
+            "pipeline": { "input": "./assets", "output": "./out" },
+            "version": 2,
+        });
+
+        let merged = merge_config(existing, &config, None, ConfigFormat::Toml).unwrap();
+        assert_eq!(
+            merged,
+            r#"version = 2
+# Hand-maintained settings
+[pipeline]
+# where assets come from
+input = "./assets"   # trailing note
+threads = 4       # not managed by myenv
+output = "./out"
+
+[extra]
+debug = true
+"#
+        );
+
+        // Equal values keep their original quoting
+        let config = serde_json::json!({ "pipeline": { "input": "./old" } });
+        let merged = merge_config(existing, &config, None, ConfigFormat::Toml).unwrap();
+        assert_eq!(merged, existing);
+    }
+
+    #[test]
+    fn json_and_yaml_merge_keep_unmanaged_keys() {
+        let config = serde_json::json!({ "server": { "port": 8080 } });
+
+        let merged = merge_config(
+            r#"{"server": {"port": 80, "host": "localhost"}, "theme": "dark"}"#,
+            &config,
+            None,
+            ConfigFormat::Json,
+        )
+        .unwrap();
+        let merged: serde_json::Value = serde_json::from_str(&merged).unwrap();
+        assert_eq!(
+            merged,
+            serde_json::json!({
+                "server": { "port": 8080, "host": "localhost" },
+                "theme": "dark",
+            })
+        );
+
+        let merged = merge_config(
+            "theme: dark\nserver:\n  port: 80\n  host: localhost\n",
+            &config,
+            None,
+            ConfigFormat::Yaml,
+        )
+        .unwrap();
+        assert_eq!(
+            merged,
+            "theme: dark\nserver:\n  port: 8080\n  host: localhost\n"
+        );
+    }
+
+    #[test]
+    fn merge_removes_keys_dropped_since_last_generation() {
+        let previous = serde_json::json!({
+            "pipeline": { "input": "./assets", "old": true },
+            "legacy": 1,
+        });
+        let config = serde_json::json!({ "pipeline": { "input": "./assets" } });
+
+        let merged = merge_config(
+            "legacy = 1\n\n[pipeline]\ninput = \"./assets\"\nold = true\nthreads = 4\n",
+            &config,
+            Some(&previous),
+            ConfigFormat::Toml,
+        )
+        .unwrap();
+        assert_eq!(merged, "\n[pipeline]\ninput = \"./assets\"\nthreads = 4\n");
+
+        let merged = merge_config(
+            r#"{"legacy": 1, "pipeline": {"input": "./assets", "old": true, "threads": 4}}"#,
+            &config,
+            Some(&previous),
+            ConfigFormat::Json,
+        )
+        .unwrap();
+        let merged: serde_json::Value = serde_json::from_str(&merged).unwrap();
+        assert_eq!(
+            merged,
+            serde_json::json!({ "pipeline": { "input": "./assets", "threads": 4 } })
+        );
+
+        let merged = merge_config(
+            "legacy: 1\npipeline:\n  input: ./assets\n  old: true\n  threads: 4\n",
+            &config,
+            Some(&previous),
+            ConfigFormat::Yaml,
+        )
+        .unwrap();
+        assert_eq!(merged, "pipeline:\n  input: ./assets\n  threads: 4\n");
+    }
+}
@@ -1,11 +1,18 @@
 //! Pull tool configs back into manifest format.
 
-use crate::schema::{ConfigFormat, SchemaError, SchemaProvider};
+use crate::expand::{Resolver, scalar_to_string};
+use crate::generate::{fetch_schemas, toml_to_json};
+use crate::manifest::{ConfigTarget, INSTANCES_KEY, Manifest, TOOL_SETTINGS_KEY};
+use crate::merge::merge_table;
+use crate::schema::{ConfigFormat, SchemaError, SchemaProvider, ToolSchema};
+use myenv_template::Template;
+use serde::Serialize;
 use std::fs;
 use std::path::Path;
+use toml_edit::DocumentMut;
 
 /// Result of pulling a tool config.
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct PulledConfig {
     /// Tool name.
     pub tool: String,
@@ -26,34 +33,254 @@ pub enum PullError {
     ReadConfig(String, std::io::Error),
     #[error("failed to parse config for '{0}': {1}")]
     ParseConfig(String, String),
+    #[error("failed to parse manifest, not updating it: {0}")]
+    ParseManifest(String),
+    #[error("failed to serialize config for '{0}': {1}")]
+    Serialize(String, String),
 }
 
 /// Pull configs for all tools.
+///
+/// With an existing manifest, a tool with instances is pulled for each of
+/// them, and pulled values are re-templatized against the current section
+/// (see [`retemplatize`]).
 pub fn pull_configs(
     tools: &[String],
+    manifest: Option<&Manifest>,
     provider: &dyn SchemaProvider,
     base_dir: &Path,
 ) -> Result<Vec<PulledConfig>, PullError> {
     let mut results = Vec::new();
+    let mut resolver = manifest.map(|m| Resolver::new(m, false));
 
-    for tool_name in tools {
-        let result = pull_tool_config(tool_name, provider, base_dir)?;
+    let targets = manifest.map(Manifest::targets).unwrap_or_default();
+    let ids: Vec<_> = tools
+        .iter()
+        .flat_map(|name| {
+            let matching: Vec<_> = targets
+                .iter()
+                .filter(|target| target.matches(name))
+                .map(|target| target.id.clone())
+                .collect();
+            // Tools not in the manifest yet get a new section
+            if matching.is_empty() {
+                vec![name.clone()]
+            } else {
+                matching
+            }
+        })
+        .collect();
+    let schemas = fetch_schemas(provider, manifest, &ids);
+    for (tool_name, schema) in ids.iter().zip(schemas) {
+        let mut result = pull_tool_config(tool_name, schema, base_dir)?;
+        if let (Some(manifest), Some(resolver)) = (manifest, resolver.as_mut()) {
+            result.config = retemplatize(tool_name, &result.config, manifest, resolver);
+        }
         results.push(result);
     }
 
     Ok(results)
 }
 
+/// Restore `{{...}}` placeholders in a pulled config.
+///
+/// Values that still equal the expansion of the manifest's existing section
+/// keep their template as written. Other strings have any text matching a
+/// placeholder the section used (e.g. `./assets` for `{{assets}}`) turned
+/// back into that placeholder, and literal `{{` is escaped.
+pub(crate) fn retemplatize(
+    tool_name: &str,
+    pulled: &toml::Value,
+    manifest: &Manifest,
+    resolver: &mut Resolver,
+) -> toml::Value {
+    let Some(mut raw) = manifest.target_config(tool_name).as_ref().map(toml_to_json) else {
+        return json_to_toml(&escape_braces(&toml_to_json(pulled)));
+    };
+    if let Some(obj) = raw.as_object_mut() {
+        obj.remove(TOOL_SETTINGS_KEY);
+    }
+    let (expanded, _) = resolver.expand_tool(tool_name);
+
+    // Placeholders used anywhere in the section, with their rendered text
+    let mut sources = Vec::new();
+    collect_placeholders(&raw, &mut sources);
+    let mut candidates: Vec<(String, String)> = sources
+        .into_iter()
+        .filter_map(|(source, template)| {
+            let p = template.as_placeholder()?;
+            let value = resolver.resolve(&p.name);
+            let text = p.apply(value.as_ref().and_then(scalar_to_string))?;
+            (!text.is_empty()).then_some((text, source))
+        })
+        .collect();
+    // Prefer the longest match, e.g. `./assets/raw` over `./assets`
+    candidates.sort_by(|a, b| b.0.len().cmp(&a.0.len()).then_with(|| a.1.cmp(&b.1)));
+    candidates.dedup_by(|a, b| a.0 == b.0);
+
+    let restored = restore(
+        &toml_to_json(pulled),
+        Some(&raw),
+        Some(&expanded),
+        &candidates,
+    );
+    json_to_toml(&instance_overrides(tool_name, restored, manifest))
+}
+
+/// Drop values an instance inherits unchanged from its tool's section, so
+/// only what differs is written under `[<tool>.instances.<name>]`.
+fn instance_overrides(
+    id: &str,
+    mut config: serde_json::Value,
+    manifest: &Manifest,
+) -> serde_json::Value {
+    let target = ConfigTarget::from_id(id);
+    let (Some(instance), Some(section)) =
+        (&target.instance, manifest.tool_configs.get(&target.tool))
+    else {
+        return config;
+    };
+    let own = section
+        .get(INSTANCES_KEY)
+        .and_then(|instances| instances.get(instance));
+    if let Some(obj) = config.as_object_mut() {
+        obj.retain(|key, value| {
+            own.is_some_and(|own| own.get(key).is_some())
+                || section.get(key).map(toml_to_json).as_ref() != Some(value)
+        });
+    }
+    config
+}
+
+/// Collect every single-placeholder string, keyed by its source text.
+fn collect_placeholders(value: &serde_json::Value, out: &mut Vec<(String, Template)>) {
+    match value {
+        serde_json::Value::String(s) => {
+            let Ok(template) = Template::parse(s) else {
+                return;
+            };
+            for p in template.placeholders() {
+                if let Ok(single) = Template::parse(&p.source) {
+                    out.push((p.source.clone(), single));
+                }
+            }
+        }
+        serde_json::Value::Array(arr) => arr.iter().for_each(|v| collect_placeholders(v, out)),
+        serde_json::Value::Object(obj) => obj.values().for_each(|v| collect_placeholders(v, out)),
+        _ => {}
+    }
+}
+
+/// Walk a pulled value alongside the raw and expanded manifest values.
+fn restore(
+    pulled: &serde_json::Value,
+    raw: Option<&serde_json::Value>,
+    expanded: Option<&serde_json::Value>,
+    candidates: &[(String, String)],
+) -> serde_json::Value {
+    if let (Some(raw), Some(expanded)) = (raw, expanded)
+        && expanded == pulled
+    {
+        return raw.clone();
+    }
+
+    match pulled {
+        serde_json::Value::String(s) => serde_json::Value::String(substitute(s, candidates)),
+        serde_json::Value::Array(arr) => serde_json::Value::Array(
+            arr.iter()
+                .enumerate()
+                .map(|(i, v)| {
+                    restore(
+                        v,
+                        raw.and_then(|r| r.get(i)),
+                        expanded.and_then(|e| e.get(i)),
+                        candidates,
+                    )
+                })
+                .collect(),
+        ),
+        serde_json::Value::Object(obj) => serde_json::Value::Object(
+            obj.iter()
+                .map(|(k, v)| {
+                    let v = restore(
+                        v,
+                        raw.and_then(|r| r.get(k)),
+                        expanded.and_then(|e| e.get(k)),
+                        candidates,
+                    );
+                    (k.clone(), v)
+                })
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+/// Replace rendered placeholder text with the placeholder, escaping any
+/// literal `{{`.
+///
+/// Text is only replaced on word boundaries, so `8080` for `{{port}}` is
+/// not found inside `18080`, nor `game` inside `./endgame`.
+fn substitute(s: &str, candidates: &[(String, String)]) -> String {
+    let mut out = String::new();
+    let mut rest = s;
+    let mut prev = None;
+    'scan: while let Some(c) = rest.chars().next() {
+        if rest.starts_with("{{") {
+            pair_backslashes(&mut out);
+            out.push_str("\\{{");
+            rest = &rest[2..];
+            prev = Some('{');
+            continue;
+        }
+        for (text, source) in candidates {
+            if let Some(after) = rest.strip_prefix(text.as_str())
+                && on_boundaries(prev, text, after.chars().next())
+            {
+                pair_backslashes(&mut out);
+                out.push_str(source);
+                rest = after;
+                prev = text.chars().next_back();
+                continue 'scan;
+            }
+        }
+        out.push(c);
+        rest = &rest[c.len_utf8()..];
+        prev = Some(c);
+    }
+    out
+}
+
+/// Double the backslashes at the end of `out`, which is about to be followed
+/// by `{{`, so they stay literal instead of escaping the braces.
+fn pair_backslashes(out: &mut String) {
+    let slashes = out.len() - out.trim_end_matches('\\').len();
+    out.push_str(&"\\".repeat(slashes));
+}
+
+/// Whether `text`, found between `before` and `after`, doesn't split a
+/// word (letters, digits and `_`) on either side.
+fn on_boundaries(before: Option<char>, text: &str, after: Option<char>) -> bool {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    let splits = |outside: Option<char>, edge: Option<char>| {
+        outside.is_some_and(is_word) && edge.is_some_and(is_word)
+    };
+    !splits(before, text.chars().next()) && !splits(after, text.chars().next_back())
+}
+
+/// Escape literal `{{` in every string of a value.
+fn escape_braces(value: &serde_json::Value) -> serde_json::Value {
+    restore(value, None, None, &[])
+}
+
 /// Pull config for a single tool.
 fn pull_tool_config(
     tool_name: &str,
-    provider: &dyn SchemaProvider,
+    schema: Result<ToolSchema, SchemaError>,
     base_dir: &Path,
 ) -> Result<PulledConfig, PullError> {
-    // Fetch schema to know where config lives
-    let schema = provider
-        .fetch(tool_name)
-        .map_err(|e| PullError::SchemaFetch(tool_name.to_string(), e))?;
+    // The schema says where the config lives
+    let schema = schema.map_err(|e| PullError::SchemaFetch(tool_name.to_string(), e))?;
 
     let config_path = base_dir.join(&schema.config_path);
 
@@ -77,7 +304,7 @@ fn pull_tool_config(
 }
 
 /// Parse config from string based on format.
-fn parse_config(
+pub(crate) fn parse_config(
     contents: &str,
     format: ConfigFormat,
     tool_name: &str,
@@ -124,37 +351,72 @@ fn json_to_toml(value: &serde_json::Value) -> toml::Value {
     }
 }
 
-/// Merge pulled configs into a manifest TOML structure.
+/// Merge pulled configs into a manifest.
+///
+/// The existing manifest is edited in place: only the pulled tool sections
+/// change, and comments, blank lines and key order elsewhere are kept.
+/// Within a pulled section, values that didn't change keep their formatting
+/// and the `_myenv` settings table is kept.
 pub fn merge_to_manifest(
     pulled: &[PulledConfig],
     existing: Option<&str>,
 ) -> Result<String, PullError> {
-    let mut table: toml::Table = if let Some(s) = existing {
-        toml::from_str(s).unwrap_or_default()
-    } else {
-        toml::Table::new()
+    let mut doc: DocumentMut = match existing {
+        Some(s) => s
+            .parse()
+            .map_err(|e: toml_edit::TomlError| PullError::ParseManifest(e.to_string()))?,
+        None => DocumentMut::new(),
     };
 
     // Ensure project section exists
-    if !table.contains_key("project") {
-        let mut project = toml::Table::new();
-        project.insert(
-            "name".to_string(),
-            toml::Value::String("my-project".to_string()),
-        );
-        project.insert(
-            "version".to_string(),
-            toml::Value::String("0.1.0".to_string()),
-        );
-        table.insert("project".to_string(), toml::Value::Table(project));
+    if !doc.contains_key("project") {
+        let mut project = toml_edit::Table::new();
+        project.insert("name", toml_edit::value("my-project"));
+        project.insert("version", toml_edit::value("0.1.0"));
+        doc.insert("project", toml_edit::Item::Table(project));
     }
 
     // Update tool sections
     for config in pulled {
-        table.insert(config.tool.clone(), config.config.clone());
+        let section = toml_edit::ser::to_document(&config.config)
+            .map_err(|e| PullError::Serialize(config.tool.clone(), e.to_string()))?;
+        let target = ConfigTarget::from_id(&config.tool);
+        let item = match &target.instance {
+            Some(instance) => {
+                let instances = parent_table(doc.as_table_mut(), &target.tool)
+                    .and_then(|tool| parent_table(tool, INSTANCES_KEY))
+                    .ok_or_else(|| {
+                        PullError::Serialize(
+                            config.tool.clone(),
+                            format!("[{}] is not a table", target.tool),
+                        )
+                    })?;
+                instances.entry(instance).or_insert(toml_edit::Item::None)
+            }
+            None => &mut doc[&target.tool],
+        };
+        match item.as_table_like_mut() {
+            Some(existing) => merge_table(existing, section.as_table(), true),
+            None => *item = toml_edit::Item::Table(section.as_table().clone()),
+        }
     }
 
-    Ok(toml::to_string_pretty(&table).unwrap())
+    Ok(doc.to_string())
+}
+
+/// A table holding other tables, created without a header if missing.
+fn parent_table<'a>(
+    table: &'a mut toml_edit::Table,
+    key: &str,
+) -> Option<&'a mut toml_edit::Table> {
+    table
+        .entry(key)
+        .or_insert_with(|| {
+            let mut table = toml_edit::Table::new();
+            table.set_implicit(true);
+            toml_edit::Item::Table(table)
+        })
+        .as_table_mut()
 }
 
 #[cfg(test)]
@@ -219,4 +481,248 @@ foo = "bar"
         assert!(result.contains("foo = \"bar\""));
         assert!(result.contains("[mytool]"));
     }
+
+    #[test]
+    fn merge_keeps_comments_and_other_sections() {
+        let existing = r#"# Game manifest
+[project]
+name = "game" # display name
+
+[variables]
+assets = "./assets"
+
+# Extractor settings
+[siphon]
+# where the dump lives
+source = "./dump/game.exe"
+strategy = "gms2"
+obsolete = true
+
+[siphon._myenv]
+merge = true
+
+[dew]
+input = "{{assets}}/raw"
+"#;
+
+        let pulled = vec![PulledConfig {
+            tool: "siphon".to_string(),
+            path: ".siphon/config.toml".into(),
+            config: toml::toml! {
+                source = "./dump/game.exe"
+                strategy = "unity"
+                threads = 4
+            }
+            .into(),
+        }];
+
+        let result = merge_to_manifest(&pulled, Some(existing)).unwrap();
+        assert_eq!(
+            result,
+            r#"# Game manifest
+[project]
+name = "game" # display name
+
+[variables]
+assets = "./assets"
+
+# Extractor settings
+[siphon]
+# where the dump lives
+source = "./dump/game.exe"
+strategy = "unity"
+threads = 4
+
+[siphon._myenv]
+merge = true
+
+[dew]
+input = "{{assets}}/raw"
+"#
+        );
+    }
+
+    #[test]
+    fn retemplatize_restores_placeholders() {
+        let manifest = Manifest::parse(
+            r#"
+            [project]
+            name = "My Game"
+
+            [variables]
+            assets = "./assets"
+            port = 8080
+
+            [dew]
+            input = "{{assets}}/raw"
+            port = "{{port}}"
+            crate_name = "{{name | snake}}"
+            output = "./build"
+        "#,
+            Path::new("."),
+        )
+        .unwrap();
+        let pulled: toml::Value = toml::toml! {
+            input = "./assets/raw"
+            port = 8080
+            crate_name = "my_game"
+            output = "./assets/processed"
+            extra = ["./assets/a", "./elsewhere"]
+            note = "use {{braces}}"
+        }
+        .into();
+
+        let mut resolver = Resolver::new(&manifest, false);
+        let restored = retemplatize("dew", &pulled, &manifest, &mut resolver);
+        let expected: toml::Value = toml::toml! {
+            input = "{{assets}}/raw"
+            port = "{{port}}"
+            crate_name = "{{name | snake}}"
+            output = "{{assets}}/processed"
+            extra = ["{{assets}}/a", "./elsewhere"]
+            note = "use \\{{braces}}"
+        }
+        .into();
+        assert_eq!(restored, expected);
+
+        // A changed value is kept as pulled
+        let pulled: toml::Value = toml::toml! { port = 9000 }.into();
+        let restored = retemplatize("dew", &pulled, &manifest, &mut resolver);
+        assert_eq!(restored["port"].as_integer(), Some(9000));
+    }
+
+    #[test]
+    fn retemplatize_only_matches_whole_words() {
+        let manifest = Manifest::parse(
+            r#"
+            [project]
+            name = "game"
+
+            [variables]
+            port = 8080
+
+            [lotus]
+            url = "http://localhost:{{port}}"
+            title = "{{name}}"
+            dir = "./{{name}}"
+        "#,
+            Path::new("."),
+        )
+        .unwrap();
+        let pulled: toml::Value = toml::toml! {
+            url = "http://localhost:18080"
+            title = "endgame"
+            dir = "./endgame"
+            backup = "./game/old"
+        }
+        .into();
+
+        let mut resolver = Resolver::new(&manifest, false);
+        let restored = retemplatize("lotus", &pulled, &manifest, &mut resolver);
+        let expected: toml::Value = toml::toml! {
+            url = "http://localhost:18080"
+            title = "endgame"
+            dir = "./endgame"
+            backup = "./{{name}}/old"
+        }
+        .into();
+        assert_eq!(restored, expected);
+    }
+
+    #[test]
+    fn retemplatize_keeps_backslashes_before_braces() {
+        let manifest = Manifest::parse(
+            r#"
+            [project]
+            name = "game"
+
+            [lotus]
+            dir = 'C:\\{{name}}'
+        "#,
+            Path::new("."),
+        )
+        .unwrap();
+        let pulled: toml::Value = toml::toml! {
+            dir = "C:\\game"
+            note = "a\\{{b}}"
+        }
+        .into();
+
+        let mut resolver = Resolver::new(&manifest, false);
+        let restored = retemplatize("lotus", &pulled, &manifest, &mut resolver);
+        let expected: toml::Value = toml::toml! {
+            dir = "C:\\\\{{name}}"
+            note = "a\\\\\\{{b}}"
+        }
+        .into();
+        assert_eq!(restored, expected);
+    }
+
+    #[test]
+    fn merge_refuses_unparseable_manifest() {
+        let pulled = vec![PulledConfig {
+            tool: "mytool".to_string(),
+            path: ".mytool/config.toml".into(),
+            config: toml::toml! {
+                source = "./input"
+            }
+            .into(),
+        }];
+
+        let err = merge_to_manifest(&pulled, Some("[project\nname = ")).unwrap_err();
+        assert!(matches!(err, PullError::ParseManifest(_)));
+    }
+
+    #[test]
+    fn pull_writes_instance_overrides() {
+        let existing = r#"[project]
+name = "test"
+
+[dew]
+threads = 4
+
+[dew.instances.sprites]
+input = "./sprites"
+"#;
+        let manifest = Manifest::parse(existing, Path::new(".")).unwrap();
+        let mut resolver = Resolver::new(&manifest, false);
+
+        // Values inherited from [dew] are not repeated in the instance
+        let pulled: toml::Value = toml::toml! {
+            input = "./sprites"
+            threads = 4
+            output = "./out"
+        }
+        .into();
+        let config = retemplatize("dew.sprites", &pulled, &manifest, &mut resolver);
+        let pulled = vec![PulledConfig {
+            tool: "dew.sprites".to_string(),
+            path: ".dew/sprites.toml".into(),
+            config,
+        }];
+        let result = merge_to_manifest(&pulled, Some(existing)).unwrap();
+        assert_eq!(
+            result,
+            r#"[project]
+name = "test"
+
+[dew]
+threads = 4
+
+[dew.instances.sprites]
+input = "./sprites"
+output = "./out"
+"#
+        );
+
+        // New instances get their own table
+        let pulled = vec![PulledConfig {
+            tool: "dew.audio".to_string(),
+            path: ".dew/audio.toml".into(),
+            config: toml::toml! { input = "./audio" }.into(),
+        }];
+        let result = merge_to_manifest(&pulled, None).unwrap();
+        assert!(result.contains("[dew.instances.audio]\ninput = \"./audio\"\n"));
+        assert!(!result.contains("[dew]\n"));
+    }
 }
@@ -1,10 +1,20 @@
 //! Schema discovery from tools.
 //!
 //! Tools provide their configuration schema via the `--schema` flag convention.
+//! Schemas can also be read from `<tool>.json` files, for tools that don't
+//! implement `--schema` yet or aren't installed (e.g. on CI runners).
 
+use crate::manifest::{Manifest, Settings};
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
-use std::process::Command;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Default time a tool gets to print its schema.
+pub const DEFAULT_SCHEMA_TIMEOUT: Duration = Duration::from_secs(30);
 
 /// Tool schema response from `<tool> --schema`.
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -41,39 +51,391 @@ pub enum SchemaError {
     InvalidResponse(String, serde_json::Error),
     #[error("failed to execute tool '{0}': {1}")]
     Exec(String, std::io::Error),
+    #[error("config_path '{path}' for '{tool}' is invalid: {source}")]
+    ConfigPath {
+        tool: String,
+        path: String,
+        source: Box<myenv_template::TemplateError>,
+    },
+    #[error("tool '{tool}' did not print its schema within {timeout:?}")]
+    Timeout { tool: String, timeout: Duration },
+    #[error("no schema file for '{tool}' at {}", path.display())]
+    NoSchemaFile { tool: String, path: PathBuf },
+    #[error("failed to read schema file {}: {source}", path.display())]
+    ReadFile {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("invalid schema file {}: {source}", path.display())]
+    InvalidFile {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+}
+
+/// A place `ChainSchemaProvider::from_settings` looks for schemas.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SchemaSource {
+    /// Run `<tool> --schema`.
+    Cli,
+    /// `<tool>.json` in the project's schema directory.
+    Project,
+    /// `<tool>.json` in the user's config directory
+    /// (e.g. `~/.config/myenv/schemas`).
+    User,
 }
 
 /// Provides schemas for tools.
 pub trait SchemaProvider {
     /// Fetch the schema for a tool by name.
     fn fetch(&self, tool: &str) -> Result<ToolSchema, SchemaError>;
+
+    /// Fetch schemas for several tools, in the order given.
+    ///
+    /// Fetches one at a time by default; providers that can fetch
+    /// concurrently override this.
+    fn fetch_all(&self, tools: &[&str]) -> Vec<Result<ToolSchema, SchemaError>> {
+        tools.iter().map(|tool| self.fetch(tool)).collect()
+    }
+
+    /// A key that changes whenever the tool's schema might have, for
+    /// [`CachingSchemaProvider`](crate::CachingSchemaProvider). `None` means
+    /// the schema can't be cached.
+    fn cache_key(&self, _tool: &str) -> Option<String> {
+        None
+    }
+}
+
+impl<P: SchemaProvider + ?Sized> SchemaProvider for Box<P> {
+    fn fetch(&self, tool: &str) -> Result<ToolSchema, SchemaError> {
+        (**self).fetch(tool)
+    }
+
+    fn fetch_all(&self, tools: &[&str]) -> Vec<Result<ToolSchema, SchemaError>> {
+        (**self).fetch_all(tools)
+    }
+
+    fn cache_key(&self, tool: &str) -> Option<String> {
+        (**self).cache_key(tool)
+    }
 }
 
 /// Fetches schemas by invoking `<tool> --schema`.
-#[derive(Debug, Default, Clone)]
-pub struct CliSchemaProvider;
+///
+/// [`fetch_all`](SchemaProvider::fetch_all) runs the tools concurrently.
+/// A tool still running after the timeout is killed.
+#[derive(Debug, Clone)]
+pub struct CliSchemaProvider {
+    timeout: Option<Duration>,
+    /// Program and arguments per tool, from `bin` and `schema_args`.
+    commands: BTreeMap<String, (PathBuf, Vec<String>)>,
+    /// Tools with a `bin` override, whose schemas are never cached.
+    uncached: BTreeSet<String>,
+}
+
+impl Default for CliSchemaProvider {
+    fn default() -> Self {
+        Self {
+            timeout: Some(DEFAULT_SCHEMA_TIMEOUT),
+            commands: BTreeMap::new(),
+            uncached: BTreeSet::new(),
+        }
+    }
+}
+
+impl CliSchemaProvider {
+    /// Create a provider with the default timeout.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set how long each tool gets to print its schema (`None` waits forever).
+    pub fn with_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Use the `bin` and `schema_args` from each tool's `_myenv` settings.
+    ///
+    /// A `bin` with a directory (e.g. `./target/release/siphon`) is
+    /// relative to `base_dir`; a bare name is looked up on `PATH`. Schemas
+    /// from an overridden `bin` aren't cached, since it may be a wrapper
+    /// script whose output changes without the file itself changing.
+    pub fn with_tool_overrides(mut self, manifest: &Manifest, base_dir: &Path) -> Self {
+        for tool in manifest.tool_configs.keys() {
+            let settings = crate::generate::tool_settings(manifest, tool);
+            if settings.bin.is_none() && settings.schema_args.is_none() {
+                continue;
+            }
+            if settings.bin.is_some() {
+                self.uncached.insert(tool.clone());
+            }
+            let program = match settings.bin {
+                Some(bin) if bin.components().count() > 1 => base_dir.join(bin),
+                Some(bin) => bin,
+                None => PathBuf::from(tool),
+            };
+            let args = settings
+                .schema_args
+                .unwrap_or_else(|| vec!["--schema".to_string()]);
+            self.commands.insert(tool.clone(), (program, args));
+        }
+        self
+    }
+
+    /// The program and arguments that print a tool's schema.
+    fn command<'a>(&'a self, tool: &'a str) -> (&'a Path, Vec<&'a str>) {
+        match self.commands.get(tool) {
+            Some((program, args)) => (program, args.iter().map(String::as_str).collect()),
+            None => (Path::new(tool), vec!["--schema"]),
+        }
+    }
+}
 
 impl SchemaProvider for CliSchemaProvider {
     fn fetch(&self, tool: &str) -> Result<ToolSchema, SchemaError> {
-        let output = Command::new(tool).arg("--schema").output().map_err(|e| {
-            if e.kind() == std::io::ErrorKind::NotFound {
-                SchemaError::ToolNotFound(tool.to_string())
-            } else {
-                SchemaError::Exec(tool.to_string(), e)
-            }
-        })?;
+        let exec_error = |e: std::io::Error| SchemaError::Exec(tool.to_string(), e);
+        let (program, args) = self.command(tool);
+
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| {
+                if e.kind() == std::io::ErrorKind::NotFound {
+                    SchemaError::ToolNotFound(program.display().to_string())
+                } else {
+                    exec_error(e)
+                }
+            })?;
 
-        if !output.status.success() {
+        // Drain both pipes so a chatty tool can't block on a full pipe
+        let stdout = read_pipe(child.stdout.take());
+        let stderr = read_pipe(child.stderr.take());
+
+        let status = match self.timeout {
+            Some(timeout) => match wait_timeout(&mut child, timeout).map_err(exec_error)? {
+                Some(status) => status,
+                None => {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    return Err(SchemaError::Timeout {
+                        tool: tool.to_string(),
+                        timeout,
+                    });
+                }
+            },
+            None => child.wait().map_err(exec_error)?,
+        };
+        let stdout = stdout.join().unwrap_or_default();
+        let stderr = stderr.join().unwrap_or_default();
+
+        if !status.success() {
             return Err(SchemaError::ToolFailed {
                 tool: tool.to_string(),
-                code: output.status.code().unwrap_or(-1),
-                stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+                code: status.code().unwrap_or(-1),
+                stderr: String::from_utf8_lossy(&stderr).into_owned(),
             });
         }
 
-        serde_json::from_slice(&output.stdout)
+        serde_json::from_slice(&stdout)
             .map_err(|e| SchemaError::InvalidResponse(tool.to_string(), e))
     }
+
+    fn fetch_all(&self, tools: &[&str]) -> Vec<Result<ToolSchema, SchemaError>> {
+        thread::scope(|scope| {
+            let handles: Vec<_> = tools
+                .iter()
+                .map(|tool| scope.spawn(move || self.fetch(tool)))
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| {
+                    handle
+                        .join()
+                        .unwrap_or_else(|e| std::panic::resume_unwind(e))
+                })
+                .collect()
+        })
+    }
+
+    /// The resolved binary's path, size and modification time, plus the
+    /// schema arguments. `None` for tools with a `bin` override.
+    fn cache_key(&self, tool: &str) -> Option<String> {
+        if self.uncached.contains(tool) {
+            return None;
+        }
+        let (program, args) = self.command(tool);
+        let binary = find_executable(program)?;
+        let metadata = binary.metadata().ok()?;
+        let modified = metadata
+            .modified()
+            .ok()?
+            .duration_since(std::time::UNIX_EPOCH)
+            .ok()?;
+        Some(format!(
+            "{} {} {} {}",
+            binary.display(),
+            metadata.len(),
+            modified.as_nanos(),
+            args.join(" ")
+        ))
+    }
+}
+
+/// Find the file `Command::new(tool)` would run.
+fn find_executable(tool: &Path) -> Option<PathBuf> {
+    if tool.components().count() > 1 {
+        return tool.is_file().then(|| tool.to_path_buf());
+    }
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join(tool))
+        .find(|path| path.is_file())
+}
+
+/// Reads schemas from `<tool>.json` files in a directory.
+///
+/// The files hold the same JSON a tool prints for `--schema`.
+#[derive(Debug, Clone)]
+pub struct FileSchemaProvider {
+    dir: PathBuf,
+}
+
+impl FileSchemaProvider {
+    /// Read schemas from a directory.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Read schemas from the user's config directory, if there is one.
+    pub fn user() -> Option<Self> {
+        dirs::config_dir().map(|dir| Self::new(dir.join("myenv").join("schemas")))
+    }
+}
+
+impl SchemaProvider for FileSchemaProvider {
+    fn fetch(&self, tool: &str) -> Result<ToolSchema, SchemaError> {
+        let path = self.dir.join(format!("{tool}.json"));
+        let contents = match std::fs::read(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(SchemaError::NoSchemaFile {
+                    tool: tool.to_string(),
+                    path,
+                });
+            }
+            Err(source) => return Err(SchemaError::ReadFile { path, source }),
+        };
+        serde_json::from_slice(&contents)
+            .map_err(|source| SchemaError::InvalidFile { path, source })
+    }
+}
+
+/// Tries providers in order, using the first schema found.
+///
+/// If every provider fails, the first provider's error is returned.
+#[derive(Default)]
+pub struct ChainSchemaProvider {
+    providers: Vec<Box<dyn SchemaProvider>>,
+}
+
+impl ChainSchemaProvider {
+    /// Try `providers` in order.
+    pub fn new(providers: Vec<Box<dyn SchemaProvider>>) -> Self {
+        Self { providers }
+    }
+
+    /// Build the chain configured by `schema_sources` in `[myenv]`.
+    ///
+    /// `cli` fetches schemas from tool binaries, so callers choose its
+    /// timeout and caching. Project schema files are looked up relative to
+    /// `base_dir`.
+    pub fn from_settings(
+        settings: &Settings,
+        base_dir: &Path,
+        cli: Box<dyn SchemaProvider>,
+    ) -> Self {
+        let mut cli = Some(cli);
+        let providers = settings
+            .schema_sources()
+            .iter()
+            .filter_map(|source| -> Option<Box<dyn SchemaProvider>> {
+                match source {
+                    SchemaSource::Cli => cli.take(),
+                    SchemaSource::Project => Some(Box::new(FileSchemaProvider::new(
+                        base_dir.join(settings.schema_dir()),
+                    ))),
+                    SchemaSource::User => Some(Box::new(FileSchemaProvider::user()?)),
+                }
+            })
+            .collect();
+        Self::new(providers)
+    }
+}
+
+impl SchemaProvider for ChainSchemaProvider {
+    fn fetch(&self, tool: &str) -> Result<ToolSchema, SchemaError> {
+        self.fetch_all(&[tool]).remove(0)
+    }
+
+    fn fetch_all(&self, tools: &[&str]) -> Vec<Result<ToolSchema, SchemaError>> {
+        let mut results: Vec<Option<Result<ToolSchema, SchemaError>>> =
+            tools.iter().map(|_| None).collect();
+
+        for provider in &self.providers {
+            // Ask the next provider only about tools without a schema yet
+            let pending: Vec<_> = (0..tools.len())
+                .filter(|&i| !matches!(results[i], Some(Ok(_))))
+                .collect();
+            if pending.is_empty() {
+                break;
+            }
+            let names: Vec<_> = pending.iter().map(|&i| tools[i]).collect();
+            for (i, result) in pending.into_iter().zip(provider.fetch_all(&names)) {
+                // Keep the first error, since it comes from the preferred source
+                if result.is_ok() || results[i].is_none() {
+                    results[i] = Some(result);
+                }
+            }
+        }
+
+        tools
+            .iter()
+            .zip(results)
+            .map(|(tool, result)| {
+                result.unwrap_or_else(|| Err(SchemaError::ToolNotFound(tool.to_string())))
+            })
+            .collect()
+    }
+}
+
+/// Read a child's pipe to the end on a background thread.
+fn read_pipe(pipe: Option<impl Read + Send + 'static>) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buf = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut buf);
+        }
+        buf
+    })
+}
+
+/// Wait for a child to exit, giving up after `timeout`.
+fn wait_timeout(child: &mut Child, timeout: Duration) -> std::io::Result<Option<ExitStatus>> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            return Ok(None);
+        }
+        thread::sleep((deadline - now).min(Duration::from_millis(10)));
+    }
 }
 
 #[cfg(test)]
@@ -121,4 +483,114 @@ mod tests {
         let schema: ToolSchema = serde_json::from_str(json).unwrap();
         assert_eq!(schema.format, ConfigFormat::Yaml);
     }
+
+    #[test]
+    fn chain_falls_back_to_schema_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let schemas = dir.path().join("schemas");
+        std::fs::create_dir_all(&schemas).unwrap();
+        std::fs::write(
+            schemas.join("myenv-test-dew.json"),
+            r#"{"config_path": ".dew/config.toml", "format": "toml", "schema": {}}"#,
+        )
+        .unwrap();
+        std::fs::write(schemas.join("broken.json"), "not json").unwrap();
+
+        let settings: Settings = toml::from_str(r#"schema_sources = ["cli", "project"]"#).unwrap();
+        let cli = CliSchemaProvider::new();
+        let chain = ChainSchemaProvider::from_settings(&settings, dir.path(), Box::new(cli));
+
+        let results = chain.fetch_all(&["myenv-test-no-such-tool", "myenv-test-dew"]);
+        // The first source's error wins when nothing has a schema
+        assert!(matches!(&results[0], Err(SchemaError::ToolNotFound(_))));
+        // A tool that isn't installed falls back to its schema file
+        assert_eq!(
+            results[1].as_ref().unwrap().config_path,
+            PathBuf::from(".dew/config.toml")
+        );
+
+        let file = FileSchemaProvider::new(&schemas);
+        assert!(matches!(
+            file.fetch("broken"),
+            Err(SchemaError::InvalidFile { .. })
+        ));
+        assert!(matches!(
+            file.fetch("lotus"),
+            Err(SchemaError::NoSchemaFile { .. })
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn cli_fetch_times_out_and_kills_tool() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let write_tool = |name: &str, script: &str| {
+            let path = dir.path().join(name);
+            std::fs::write(&path, format!("#!/bin/sh\n{script}\n")).unwrap();
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+            path.to_str().unwrap().to_string()
+        };
+        let slow = write_tool("slow", "exec sleep 10");
+        let fast = write_tool(
+            "fast",
+            r#"echo '{"config_path": "fast.toml", "format": "toml", "schema": {}}'"#,
+        );
+
+        let provider = CliSchemaProvider::new().with_timeout(Some(Duration::from_millis(200)));
+        let start = Instant::now();
+        let results = provider.fetch_all(&[&slow, &fast]);
+        assert!(start.elapsed() < Duration::from_secs(5));
+
+        assert!(matches!(
+            &results[0],
+            Err(SchemaError::Timeout { timeout, .. }) if *timeout == Duration::from_millis(200)
+        ));
+        assert_eq!(
+            results[1].as_ref().unwrap().config_path,
+            PathBuf::from("fast.toml")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn cli_uses_tool_bin_and_schema_args() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let bin = dir.path().join("target/release/siphon");
+        std::fs::create_dir_all(bin.parent().unwrap()).unwrap();
+        std::fs::write(
+            &bin,
+            r#"#!/bin/sh
+[ "$1 $2" = "schema --json" ] || exit 3
+echo '{"config_path": "siphon.toml", "format": "toml", "schema": {}}'
+"#,
+        )
+        .unwrap();
+        std::fs::set_permissions(&bin, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let manifest = Manifest::parse(
+            r#"
+            [project]
+            name = "test"
+
+            [siphon]
+            output = "./raw"
+
+            [siphon._myenv]
+            bin = "./target/release/siphon"
+            schema_args = ["schema", "--json"]
+        "#,
+            Path::new("."),
+        )
+        .unwrap();
+        let provider = CliSchemaProvider::new().with_tool_overrides(&manifest, dir.path());
+        assert_eq!(
+            provider.fetch("siphon").unwrap().config_path,
+            PathBuf::from("siphon.toml")
+        );
+        assert_eq!(provider.cache_key("siphon"), None);
+    }
 }
@@ -0,0 +1,212 @@
+//! Generation state in `.myenv/generated.toml`.
+//!
+//! Records which files `myenv generate` wrote and a hash of their contents,
+//! so later runs can tell generated files apart from hand-written ones and
+//! notice when a generated file was edited. The generated values themselves
+//! are kept in `.myenv/snapshots/<tool>.json` as the base for `config sync`.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Directory for myenv's own state, relative to the project root.
+pub const STATE_DIR: &str = ".myenv";
+
+/// State file name inside [`STATE_DIR`].
+const STATE_FILE: &str = "generated.toml";
+
+/// Snapshot directory inside [`STATE_DIR`].
+const SNAPSHOT_DIR: &str = "snapshots";
+
+/// Files written by previous generations.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GenerationState {
+    /// Generated file per tool.
+    #[serde(default)]
+    pub tools: BTreeMap<String, GeneratedFile>,
+}
+
+/// A file written by `myenv generate`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GeneratedFile {
+    /// Path relative to the project root.
+    pub path: PathBuf,
+    /// SHA-256 of the written contents, e.g. `sha256:ab12...`.
+    pub hash: String,
+}
+
+/// Errors that can occur reading or writing generation state.
+#[derive(Debug, thiserror::Error)]
+pub enum StateError {
+    #[error("failed to access {}: {source}", path.display())]
+    Io { path: PathBuf, source: io::Error },
+    #[error("failed to parse {}: {source}", path.display())]
+    Parse {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    #[error("failed to serialize generation state: {0}")]
+    Serialize(#[from] toml::ser::Error),
+    #[error("invalid snapshot {}: {source}", path.display())]
+    Snapshot {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+}
+
+impl GenerationState {
+    /// Path of the state file for a project.
+    pub fn path(base_dir: &Path) -> PathBuf {
+        base_dir.join(STATE_DIR).join(STATE_FILE)
+    }
+
+    /// Load a project's state, or empty state if nothing was generated yet.
+    pub fn load(base_dir: &Path) -> Result<Self, StateError> {
+        let path = Self::path(base_dir);
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(source) => return Err(StateError::Io { path, source }),
+        };
+        toml::from_str(&contents).map_err(|source| StateError::Parse { path, source })
+    }
+
+    /// Write the state file atomically.
+    pub fn save(&self, base_dir: &Path) -> Result<(), StateError> {
+        let path = Self::path(base_dir);
+        let contents = toml::to_string_pretty(self)?;
+        write_atomic(&path, contents.as_bytes()).map_err(|source| StateError::Io { path, source })
+    }
+
+    /// Record that `contents` were written to `path` for a tool.
+    pub fn record(&mut self, tool: &str, base_dir: &Path, path: &Path, contents: &[u8]) {
+        let path = path.strip_prefix(base_dir).unwrap_or(path).to_path_buf();
+        self.tools.insert(
+            tool.to_string(),
+            GeneratedFile {
+                path,
+                hash: hash_contents(contents),
+            },
+        );
+    }
+
+    /// Drop a tool's record and snapshot.
+    pub fn forget(&mut self, tool: &str, base_dir: &Path) {
+        self.tools.remove(tool);
+        let _ = fs::remove_file(snapshot_path(base_dir, tool));
+    }
+
+    /// Whether `contents` found at `path` differ from what was last
+    /// generated there for a tool. Files with no record are never modified.
+    pub fn is_modified(&self, tool: &str, base_dir: &Path, path: &Path, contents: &[u8]) -> bool {
+        let path = path.strip_prefix(base_dir).unwrap_or(path);
+        self.tools
+            .get(tool)
+            .is_some_and(|file| file.path == path && file.hash != hash_contents(contents))
+    }
+}
+
+/// Path of a tool's snapshot.
+fn snapshot_path(base_dir: &Path, tool: &str) -> PathBuf {
+    base_dir
+        .join(STATE_DIR)
+        .join(SNAPSHOT_DIR)
+        .join(format!("{tool}.json"))
+}
+
+/// Store the values last generated for a tool.
+pub fn save_snapshot(
+    base_dir: &Path,
+    tool: &str,
+    config: &serde_json::Value,
+) -> Result<(), StateError> {
+    let path = snapshot_path(base_dir, tool);
+    let contents = serde_json::to_string_pretty(config).map_err(|source| StateError::Snapshot {
+        path: path.clone(),
+        source,
+    })?;
+    write_atomic(&path, contents.as_bytes()).map_err(|source| StateError::Io { path, source })
+}
+
+/// Load the values last generated for a tool, if any.
+pub fn load_snapshot(base_dir: &Path, tool: &str) -> Result<Option<serde_json::Value>, StateError> {
+    let path = snapshot_path(base_dir, tool);
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(source) => return Err(StateError::Io { path, source }),
+    };
+    serde_json::from_str(&contents)
+        .map(Some)
+        .map_err(|source| StateError::Snapshot { path, source })
+}
+
+/// Hash file contents in the format stored in the state file.
+pub fn hash_contents(contents: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(contents);
+    format!("sha256:{:x}", hasher.finalize())
+}
+
+/// Write a file via a temporary sibling and rename, so readers never see a
+/// partially written file.
+pub(crate) fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let tmp = path.with_file_name(format!(".{file_name}.{}.tmp", std::process::id()));
+    let result = fs::write(&tmp, contents).and_then(|()| fs::rename(&tmp, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn state_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(GenerationState::load(dir.path()).unwrap().tools.is_empty());
+
+        let config = dir.path().join(".dew/config.toml");
+        write_atomic(&config, b"input = \"./assets\"\n").unwrap();
+        assert_eq!(
+            fs::read_to_string(&config).unwrap(),
+            "input = \"./assets\"\n"
+        );
+
+        let mut state = GenerationState::default();
+        state.record("dew", dir.path(), &config, b"input = \"./assets\"\n");
+        state.save(dir.path()).unwrap();
+
+        let loaded = GenerationState::load(dir.path()).unwrap();
+        let dew = &loaded.tools["dew"];
+        assert_eq!(dew.path, Path::new(".dew/config.toml"));
+        assert_eq!(dew.hash, hash_contents(b"input = \"./assets\"\n"));
+        assert!(dew.hash.starts_with("sha256:"));
+
+        // No temp files are left behind
+        let leftovers: Vec<_> = fs::read_dir(dir.path().join(".dew"))
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        assert_eq!(leftovers, ["config.toml"]);
+
+        let config = serde_json::json!({"input": "./assets"});
+        save_snapshot(dir.path(), "dew", &config).unwrap();
+        assert_eq!(load_snapshot(dir.path(), "dew").unwrap(), Some(config));
+
+        let mut state = loaded;
+        state.forget("dew", dir.path());
+        assert!(state.tools.is_empty());
+        assert_eq!(load_snapshot(dir.path(), "dew").unwrap(), None);
+    }
+}
@@ -0,0 +1,500 @@
+//! Three-way sync between the manifest and tool config files.
+//!
+//! The values written by the last `generate` are the common base. For every
+//! key, a change on only one side is applied to the other; a key changed
+//! differently on both sides is a conflict and is left alone on both sides.
+
+use crate::expand::Resolver;
+use crate::generate::{
+    GenerateError, expand_tool_config, fetch_schemas, json_to_toml, render_config, toml_to_json,
+    tool_settings, validate_config, write_config,
+};
+use crate::manifest::Manifest;
+use crate::pull::{PullError, PulledConfig, merge_to_manifest, parse_config, retemplatize};
+use crate::schema::SchemaProvider;
+use crate::state::{GenerationState, StateError, load_snapshot, save_snapshot, write_atomic};
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A key changed differently in the manifest and the tool config.
+#[derive(Debug, Clone, Serialize)]
+pub struct SyncConflict {
+    /// Dotted key path including the tool name, e.g. `dew.output`.
+    pub path: String,
+    /// Value in the manifest (`None` if removed there).
+    pub manifest: Option<serde_json::Value>,
+    /// Value in the tool config (`None` if removed there).
+    pub tool: Option<serde_json::Value>,
+}
+
+/// Changes found for a single tool.
+#[derive(Debug, Clone, Serialize)]
+pub struct ToolSync {
+    /// Tool name.
+    pub tool: String,
+    /// Path of the tool's config file.
+    pub path: PathBuf,
+    /// Keys changed in the manifest and pushed to the tool config.
+    pub pushed: Vec<String>,
+    /// Keys changed in the tool config and pulled into the manifest.
+    pub pulled: Vec<String>,
+    /// Keys changed on both sides.
+    pub conflicts: Vec<SyncConflict>,
+}
+
+impl ToolSync {
+    /// Whether both sides already agree.
+    pub fn is_unchanged(&self) -> bool {
+        self.pushed.is_empty() && self.pulled.is_empty() && self.conflicts.is_empty()
+    }
+}
+
+/// Result of syncing a single tool.
+#[derive(Debug, Serialize)]
+#[serde(tag = "status", rename_all = "lowercase")]
+pub enum SyncResult {
+    /// Tool was compared and non-conflicting changes were applied (or would
+    /// be, in a dry run).
+    Synced(ToolSync),
+    /// Tool could not be synced (no schema, no config, or never generated).
+    Skipped { tool: String, reason: String },
+}
+
+/// Outcome of `sync_configs`.
+#[derive(Debug, Serialize)]
+pub struct SyncReport {
+    /// Per-tool results, in manifest order.
+    pub results: Vec<SyncResult>,
+    /// Updated manifest contents, if anything was pulled into it (written
+    /// unless it was a dry run).
+    pub manifest: Option<String>,
+}
+
+/// What syncing one tool will write, worked out before anything is written.
+struct PlannedSync {
+    tool: String,
+    path: PathBuf,
+    /// New config contents, if anything was pushed.
+    contents: Option<String>,
+    /// Contents the file will have afterwards, recorded as generated once
+    /// both sides agree.
+    record: Option<String>,
+    /// New base for the next sync.
+    snapshot: serde_json::Value,
+}
+
+/// Errors that can occur during sync.
+#[derive(Debug, thiserror::Error)]
+pub enum SyncError {
+    #[error(transparent)]
+    Generate(#[from] GenerateError),
+    #[error(transparent)]
+    Pull(#[from] PullError),
+    #[error(transparent)]
+    State(#[from] StateError),
+    #[error("failed to access {}: {source}", path.display())]
+    Manifest {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+}
+
+/// Sync every tool between the manifest and its config file.
+///
+/// Every tool is merged, and the updated configs and manifest rendered,
+/// before anything is written. Then, unless `dry_run` is set, the manifest
+/// at `manifest_path` is updated first, and only after that are tool
+/// configs written and snapshots saved, so a snapshot never holds pulled
+/// values the manifest doesn't have yet.
+pub fn sync_configs(
+    manifest: &Manifest,
+    manifest_path: &Path,
+    provider: &dyn SchemaProvider,
+    base_dir: &Path,
+    dry_run: bool,
+) -> Result<SyncReport, SyncError> {
+    let manifest_error = |source| SyncError::Manifest {
+        path: manifest_path.to_path_buf(),
+        source,
+    };
+    let manifest_source = fs::read_to_string(manifest_path).map_err(manifest_error)?;
+    let mut resolver = Resolver::new(manifest, true);
+    let mut pull_resolver = Resolver::new(manifest, false);
+    let mut state = GenerationState::load(base_dir)?;
+    let mut results = Vec::new();
+    let mut pulled = Vec::new();
+    let mut planned = Vec::new();
+
+    let ids: Vec<_> = manifest.targets().into_iter().map(|t| t.id).collect();
+    let schemas = fetch_schemas(provider, Some(manifest), &ids);
+
+    for (tool_name, schema) in ids.iter().zip(schemas) {
+        let skip = |reason: String| SyncResult::Skipped {
+            tool: tool_name.clone(),
+            reason,
+        };
+
+        let schema = match schema {
+            Ok(s) => s,
+            Err(e) => {
+                results.push(skip(e.to_string()));
+                continue;
+            }
+        };
+        let Some(base) = load_snapshot(base_dir, tool_name)? else {
+            results.push(skip(
+                "no generation record; run 'myenv generate' first".into(),
+            ));
+            continue;
+        };
+
+        let config_path = base_dir.join(&schema.config_path);
+        let contents = match fs::read_to_string(&config_path) {
+            Ok(c) => c,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                results.push(skip(format!("{} not found", config_path.display())));
+                continue;
+            }
+            Err(e) => return Err(PullError::ReadConfig(tool_name.clone(), e).into()),
+        };
+        let theirs = toml_to_json(&parse_config(&contents, schema.format, tool_name)?);
+        let ours = expand_tool_config(tool_name, &mut resolver)?;
+        let settings = tool_settings(manifest, tool_name);
+
+        let mut sync = ToolSync {
+            tool: tool_name.clone(),
+            path: config_path.clone(),
+            pushed: Vec::new(),
+            pulled: Vec::new(),
+            conflicts: Vec::new(),
+        };
+        let merged = three_way(
+            Some(&base),
+            Some(&ours),
+            Some(&theirs),
+            tool_name,
+            settings.merge,
+            &mut sync,
+        );
+        let empty = || serde_json::Value::Object(serde_json::Map::new());
+
+        if !sync.pulled.is_empty() {
+            let config = json_to_toml(&merged.manifest.unwrap_or_else(empty));
+            pulled.push(PulledConfig {
+                tool: tool_name.clone(),
+                path: config_path.clone(),
+                config: retemplatize(tool_name, &config, manifest, &mut pull_resolver),
+            });
+        }
+
+        let new_contents = if sync.pushed.is_empty() {
+            None
+        } else {
+            let config = merged.tool.unwrap_or_else(empty);
+            validate_config(tool_name, &config, &schema)?;
+            Some(render_config(
+                tool_name,
+                &settings,
+                base_dir,
+                &config_path,
+                &config,
+                schema.format,
+            )?)
+        };
+        // Once both sides agree, the current file is the generated one
+        let record = sync
+            .conflicts
+            .is_empty()
+            .then(|| new_contents.clone().unwrap_or(contents));
+        planned.push(PlannedSync {
+            tool: tool_name.clone(),
+            path: config_path,
+            contents: new_contents,
+            record,
+            snapshot: merged.base.unwrap_or_else(empty),
+        });
+
+        results.push(SyncResult::Synced(sync));
+    }
+
+    let updated = if pulled.is_empty() {
+        None
+    } else {
+        Some(merge_to_manifest(&pulled, Some(&manifest_source))?)
+    };
+    if dry_run {
+        return Ok(SyncReport {
+            results,
+            manifest: updated,
+        });
+    }
+
+    if let Some(updated) = &updated {
+        write_atomic(manifest_path, updated.as_bytes()).map_err(manifest_error)?;
+    }
+    for plan in planned {
+        if let Some(contents) = &plan.contents {
+            write_config(&plan.tool, &plan.path, contents)?;
+        }
+        if let Some(record) = &plan.record {
+            state.record(&plan.tool, base_dir, &plan.path, record.as_bytes());
+        }
+        save_snapshot(base_dir, &plan.tool, &plan.snapshot)?;
+    }
+    state.save(base_dir)?;
+
+    Ok(SyncReport {
+        results,
+        manifest: updated,
+    })
+}
+
+/// A value merged for each side; `None` means the key is absent.
+struct Merged {
+    manifest: Option<serde_json::Value>,
+    tool: Option<serde_json::Value>,
+    base: Option<serde_json::Value>,
+}
+
+/// Merge one value from the base, manifest (`ours`) and tool (`theirs`).
+///
+/// Tables are merged key by key; anything else is compared as a whole. In
+/// merge mode, keys only the tool config has are not myenv's and are
+/// ignored.
+fn three_way(
+    base: Option<&serde_json::Value>,
+    ours: Option<&serde_json::Value>,
+    theirs: Option<&serde_json::Value>,
+    path: &str,
+    merge_mode: bool,
+    sync: &mut ToolSync,
+) -> Merged {
+    let table_or_absent = |v: Option<&serde_json::Value>| v.is_none_or(|v| v.is_object());
+    if table_or_absent(base)
+        && table_or_absent(ours)
+        && table_or_absent(theirs)
+        && (ours.is_some() || theirs.is_some())
+    {
+        let empty = serde_json::Map::new();
+        let (b, o, t) = (
+            as_table(base, &empty),
+            as_table(ours, &empty),
+            as_table(theirs, &empty),
+        );
+
+        let keys: BTreeSet<&String> = b.keys().chain(o.keys()).chain(t.keys()).collect();
+        let (mut manifest, mut tool, mut new_base) = (
+            serde_json::Map::new(),
+            serde_json::Map::new(),
+            serde_json::Map::new(),
+        );
+        for key in keys {
+            if merge_mode && !b.contains_key(key) && !o.contains_key(key) {
+                continue;
+            }
+            let child = three_way(
+                b.get(key),
+                o.get(key),
+                t.get(key),
+                &format!("{path}.{key}"),
+                merge_mode,
+                sync,
+            );
+            manifest.extend(child.manifest.map(|v| (key.clone(), v)));
+            tool.extend(child.tool.map(|v| (key.clone(), v)));
+            new_base.extend(child.base.map(|v| (key.clone(), v)));
+        }
+
+        let keep = |present: bool, map: serde_json::Map<String, serde_json::Value>| {
+            (present || !map.is_empty()).then_some(serde_json::Value::Object(map))
+        };
+        return Merged {
+            manifest: keep(ours.is_some(), manifest),
+            tool: keep(theirs.is_some(), tool),
+            base: keep(base.is_some(), new_base),
+        };
+    }
+
+    let all = |v: Option<&serde_json::Value>| Merged {
+        manifest: v.cloned(),
+        tool: v.cloned(),
+        base: v.cloned(),
+    };
+    if ours == theirs {
+        all(ours)
+    } else if ours == base {
+        sync.pulled.push(path.to_string());
+        all(theirs)
+    } else if theirs == base {
+        sync.pushed.push(path.to_string());
+        all(ours)
+    } else {
+        sync.conflicts.push(SyncConflict {
+            path: path.to_string(),
+            manifest: ours.cloned(),
+            tool: theirs.cloned(),
+        });
+        Merged {
+            manifest: ours.cloned(),
+            tool: theirs.cloned(),
+            base: base.cloned(),
+        }
+    }
+}
+
+/// The entries of a table, or `empty` if the value is absent.
+fn as_table<'a>(
+    value: Option<&'a serde_json::Value>,
+    empty: &'a serde_json::Map<String, serde_json::Value>,
+) -> &'a serde_json::Map<String, serde_json::Value> {
+    value.and_then(|v| v.as_object()).unwrap_or(empty)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generate::{GenerateOptions, generate_configs};
+    use crate::schema::{ConfigFormat, SchemaError, ToolSchema};
+
+    struct DewProvider;
+
+    impl SchemaProvider for DewProvider {
+        fn fetch(&self, tool: &str) -> Result<ToolSchema, SchemaError> {
+            Ok(ToolSchema {
+                config_path: format!(".{tool}/config.toml").into(),
+                format: ConfigFormat::Toml,
+                schema: serde_json::json!({"type": "object"}),
+            })
+        }
+    }
+
+    #[test]
+    fn sync_applies_both_directions_and_reports_conflicts() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = r#"[project]
+name = "game"
+
+[variables]
+assets = "./assets"
+
+[dew]
+input = "{{assets}}/raw"
+output = "./out"
+threads = 2
+mode = "fast"
+"#;
+        let manifest = Manifest::parse(source, Path::new(".")).unwrap();
+        generate_configs(
+            &manifest,
+            &DewProvider,
+            dir.path(),
+            &GenerateOptions::default(),
+        )
+        .unwrap();
+
+        // Manifest changes output and mode; the tool config changes threads
+        // and mode
+        let source = source
+            .replace("./out", "./build")
+            .replace("\"fast\"", "\"small\"");
+        let manifest = Manifest::parse(&source, Path::new(".")).unwrap();
+        let manifest_path = dir.path().join("nursery.toml");
+        fs::write(&manifest_path, &source).unwrap();
+        let config = dir.path().join(".dew/config.toml");
+        let edited = fs::read_to_string(&config)
+            .unwrap()
+            .replace("threads = 2", "threads = 8")
+            .replace("\"fast\"", "\"slow\"");
+        fs::write(&config, edited).unwrap();
+
+        let report =
+            sync_configs(&manifest, &manifest_path, &DewProvider, dir.path(), false).unwrap();
+        let SyncResult::Synced(sync) = &report.results[0] else {
+            panic!("expected dew to sync: {:?}", report.results);
+        };
+        assert_eq!(sync.pushed, ["dew.output"]);
+        assert_eq!(sync.pulled, ["dew.threads"]);
+        assert_eq!(sync.conflicts.len(), 1);
+        assert_eq!(sync.conflicts[0].path, "dew.mode");
+        assert_eq!(sync.conflicts[0].tool, Some(serde_json::json!("slow")));
+
+        let written = fs::read_to_string(&config).unwrap();
+        assert!(written.contains("output = \"./build\""));
+        assert!(written.contains("threads = 8"));
+        assert!(written.contains("mode = \"slow\""));
+
+        let updated = report.manifest.unwrap();
+        assert_eq!(fs::read_to_string(&manifest_path).unwrap(), updated);
+        assert!(updated.contains("input = \"{{assets}}/raw\""));
+        assert!(updated.contains("threads = 8"));
+        assert!(updated.contains("mode = \"small\""));
+
+        // The conflict is still reported until one side is changed
+        let manifest = Manifest::parse(&updated, Path::new(".")).unwrap();
+        let report =
+            sync_configs(&manifest, &manifest_path, &DewProvider, dir.path(), false).unwrap();
+        let SyncResult::Synced(sync) = &report.results[0] else {
+            panic!("expected dew to sync");
+        };
+        assert!(sync.pushed.is_empty() && sync.pulled.is_empty());
+        assert_eq!(sync.conflicts.len(), 1);
+        assert!(report.manifest.is_none());
+    }
+
+    #[test]
+    fn sync_writes_nothing_if_any_tool_fails() {
+        struct StrictLotus;
+
+        impl SchemaProvider for StrictLotus {
+            fn fetch(&self, tool: &str) -> Result<ToolSchema, SchemaError> {
+                let mut schema = DewProvider.fetch(tool)?;
+                if tool == "lotus" {
+                    schema.schema = serde_json::json!({
+                        "type": "object",
+                        "properties": {"port": {"type": "integer"}},
+                    });
+                }
+                Ok(schema)
+            }
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let source = r#"[project]
+name = "game"
+
+[dew]
+threads = 2
+
+[lotus]
+port = 8080
+"#;
+        let manifest = Manifest::parse(source, Path::new(".")).unwrap();
+        generate_configs(
+            &manifest,
+            &StrictLotus,
+            dir.path(),
+            &GenerateOptions::default(),
+        )
+        .unwrap();
+        let snapshot = load_snapshot(dir.path(), "dew").unwrap();
+
+        // dew pulls a change; lotus would push an invalid port
+        let config = dir.path().join(".dew/config.toml");
+        fs::write(&config, "threads = 8\n").unwrap();
+        let source = source.replace("8080", "\"web\"");
+        let manifest_path = dir.path().join("nursery.toml");
+        fs::write(&manifest_path, &source).unwrap();
+        let manifest = Manifest::parse(&source, Path::new(".")).unwrap();
+
+        let err = sync_configs(&manifest, &manifest_path, &StrictLotus, dir.path(), false);
+        assert!(matches!(
+            err,
+            Err(SyncError::Generate(GenerateError::ValidationFailed { .. }))
+        ));
+        assert_eq!(fs::read_to_string(&manifest_path).unwrap(), source);
+        assert_eq!(load_snapshot(dir.path(), "dew").unwrap(), snapshot);
+        assert_eq!(fs::read_to_string(&config).unwrap(), "threads = 8\n");
+    }
+}
@@ -6,8 +6,9 @@ mod variables;
 
 use std::collections::HashMap;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
+pub use myenv_template::TemplateError;
 pub use resolve::{SeedResolver, SeedSource};
 pub use variables::{VariableResolver, VariableSource};
 
@@ -40,6 +41,12 @@ pub enum SeedError {
     ParseSeed(#[source] toml::de::Error),
     #[error("missing required variable: {0}")]
     MissingVariable(String),
+    #[error("template error in {file}: {source}")]
+    Template {
+        file: String,
+        #[source]
+        source: TemplateError,
+    },
 }
 
 impl Seed {
@@ -65,42 +72,57 @@ impl Seed {
             }
         }
 
-        fs::create_dir_all(dest).map_err(SeedError::CreateDir)?;
-
+        // Render everything before touching the filesystem, so a bad
+        // template doesn't leave a half-scaffolded project behind
+        let mut entries = Vec::new();
         match &self.source {
             SeedSource::Builtin(files) => {
                 for (path, contents) in *files {
-                    let expanded = if raw {
-                        contents.to_string()
-                    } else {
-                        substitute(contents, vars)
-                    };
-                    write_file(dest, path, &expanded)?;
+                    let expanded = expand(path, contents.to_string(), vars, raw)?;
+                    entries.push((PathBuf::from(path), Some(expanded)));
                 }
             }
             SeedSource::Directory(seed_dir) => {
                 let template_dir = seed_dir.join("template");
-                copy_dir(&template_dir, dest, vars, raw)?;
+                render_dir(&template_dir, Path::new(""), vars, raw, &mut entries)?;
             }
         }
 
+        fs::create_dir_all(dest).map_err(SeedError::CreateDir)?;
+        if let Err(e) = write_entries(dest, &entries) {
+            let _ = fs::remove_dir_all(dest);
+            return Err(e);
+        }
+
         Ok(())
     }
 }
 
-fn write_file(dest: &Path, relative: &str, contents: &str) -> Result<(), SeedError> {
-    let file_path = dest.join(relative);
-    if let Some(parent) = file_path.parent() {
-        fs::create_dir_all(parent).map_err(SeedError::CreateDir)?;
+/// A path relative to the destination, with the file contents, or `None`
+/// for a directory.
+type Entry = (PathBuf, Option<String>);
+
+fn expand(
+    file: &str,
+    contents: String,
+    vars: &HashMap<String, String>,
+    raw: bool,
+) -> Result<String, SeedError> {
+    if raw {
+        return Ok(contents);
     }
-    fs::write(&file_path, contents).map_err(SeedError::WriteFile)
+    substitute(&contents, vars).map_err(|source| SeedError::Template {
+        file: file.to_string(),
+        source,
+    })
 }
 
-fn copy_dir(
+fn render_dir(
     src: &Path,
-    dest: &Path,
+    relative: &Path,
     vars: &HashMap<String, String>,
     raw: bool,
+    entries: &mut Vec<Entry>,
 ) -> Result<(), SeedError> {
     if !src.exists() {
         return Ok(());
@@ -109,33 +131,44 @@ fn copy_dir(
     for entry in fs::read_dir(src).map_err(SeedError::ReadSeed)? {
         let entry = entry.map_err(SeedError::ReadSeed)?;
         let src_path = entry.path();
-        let file_name = entry.file_name();
-        let dest_path = dest.join(&file_name);
+        let rel_path = relative.join(entry.file_name());
 
         if src_path.is_dir() {
-            fs::create_dir_all(&dest_path).map_err(SeedError::CreateDir)?;
-            copy_dir(&src_path, &dest_path, vars, raw)?;
+            entries.push((rel_path.clone(), None));
+            render_dir(&src_path, &rel_path, vars, raw, entries)?;
         } else {
             let contents = fs::read_to_string(&src_path).map_err(SeedError::ReadSeed)?;
-            let expanded = if raw {
-                contents
-            } else {
-                substitute(&contents, vars)
-            };
-            fs::write(&dest_path, expanded).map_err(SeedError::WriteFile)?;
+            let expanded = expand(&src_path.display().to_string(), contents, vars, raw)?;
+            entries.push((rel_path, Some(expanded)));
         }
     }
 
     Ok(())
 }
 
-/// Simple variable substitution: replaces `{{key}}` with value.
-pub fn substitute(template: &str, vars: &HashMap<String, String>) -> String {
-    let mut result = template.to_string();
-    for (key, value) in vars {
-        result = result.replace(&format!("{{{{{key}}}}}"), value);
+fn write_entries(dest: &Path, entries: &[Entry]) -> Result<(), SeedError> {
+    for (relative, contents) in entries {
+        let path = dest.join(relative);
+        match contents {
+            None => fs::create_dir_all(&path).map_err(SeedError::CreateDir)?,
+            Some(contents) => {
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent).map_err(SeedError::CreateDir)?;
+                }
+                fs::write(&path, contents).map_err(SeedError::WriteFile)?;
+            }
+        }
     }
-    result
+    Ok(())
+}
+
+/// Variable substitution: replaces `{{key}}` with value.
+///
+/// Undefined variables are an error. Braces that don't form a placeholder
+/// with a plain name, like `${{ secrets.TOKEN }}` in a GitHub workflow, are
+/// kept as written; use `\{{` for a literal `{{` anywhere else.
+pub fn substitute(template: &str, vars: &HashMap<String, String>) -> Result<String, TemplateError> {
+    myenv_template::Template::parse_loose(template).render(|name| vars.get(name).cloned())
 }
 
 #[cfg(test)]
@@ -148,7 +181,43 @@ mod tests {
         vars.insert("name".to_string(), "my-project".to_string());
         vars.insert("version".to_string(), "1.0.0".to_string());
 
-        let result = substitute("name = \"{{name}}\"\nversion = \"{{version}}\"", &vars);
+        let result = substitute("name = \"{{name}}\"\nversion = \"{{version}}\"", &vars).unwrap();
         assert_eq!(result, "name = \"my-project\"\nversion = \"1.0.0\"");
     }
+
+    #[test]
+    fn substitute_undefined_var() {
+        let vars = HashMap::new();
+        let err = substitute("name = \"{{nmae}}\"", &vars).unwrap_err();
+        assert_eq!(err, TemplateError::Undefined("nmae".to_string()));
+    }
+
+    #[test]
+    fn substitute_keeps_foreign_braces() {
+        let vars = HashMap::from([("name".to_string(), "demo".to_string())]);
+        let workflow = "name: {{name}}\ntoken: ${{ secrets.TOKEN }}\n";
+        let result = substitute(workflow, &vars).unwrap();
+        assert_eq!(result, "name: demo\ntoken: ${{ secrets.TOKEN }}\n");
+    }
+
+    #[test]
+    fn scaffold_writes_nothing_on_template_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let template = dir.path().join("seed/template");
+        fs::create_dir_all(template.join("src")).unwrap();
+        fs::write(template.join("src/a.txt"), "{{name}}").unwrap();
+        fs::write(template.join("src/b.txt"), "{{nmae}}").unwrap();
+
+        let seed = Seed {
+            name: "test".to_string(),
+            description: String::new(),
+            variables: HashMap::new(),
+            source: SeedSource::Directory(dir.path().join("seed")),
+        };
+        let vars = HashMap::from([("name".to_string(), "demo".to_string())]);
+        let dest = dir.path().join("out");
+        let err = seed.scaffold(&dest, &vars, false).unwrap_err();
+        assert!(matches!(err, SeedError::Template { .. }));
+        assert!(!dest.exists());
+    }
 }
@@ -0,0 +1,221 @@
+//! Placeholder filters: `{{name | snake}}`, `{{out | default: "./build"}}`.
+
+use crate::TemplateError;
+
+/// A filter applied to a placeholder's value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Filter {
+    /// Use the argument if the value is undefined.
+    Default(String),
+    /// Uppercase.
+    Upper,
+    /// Lowercase.
+    Lower,
+    /// `snake_case`.
+    Snake,
+    /// `kebab-case`.
+    Kebab,
+    /// Append a path component with `/`.
+    Join(String),
+    /// Last path component.
+    Basename,
+}
+
+impl Filter {
+    /// Parse a single filter, e.g. `upper` or `default: "./build"`.
+    fn parse(s: &str) -> Result<Self, TemplateError> {
+        let (name, arg) = match s.split_once(':') {
+            Some((name, arg)) => (name.trim(), Some(parse_string(arg.trim())?)),
+            None => (s.trim(), None),
+        };
+
+        let needs_arg = |filter: fn(String) -> Filter| {
+            arg.clone()
+                .map(filter)
+                .ok_or_else(|| TemplateError::FilterArgument(name.to_string()))
+        };
+        let no_arg = |filter: Filter| match arg {
+            Some(_) => Err(TemplateError::FilterArgument(name.to_string())),
+            None => Ok(filter),
+        };
+
+        match name {
+            "default" => needs_arg(Filter::Default),
+            "join" => needs_arg(Filter::Join),
+            "upper" => no_arg(Filter::Upper),
+            "lower" => no_arg(Filter::Lower),
+            "snake" => no_arg(Filter::Snake),
+            "kebab" => no_arg(Filter::Kebab),
+            "basename" => no_arg(Filter::Basename),
+            _ => Err(TemplateError::UnknownFilter(name.to_string())),
+        }
+    }
+
+    /// Apply the filter. Only `default` does anything to an undefined value.
+    pub fn apply(&self, value: Option<String>) -> Option<String> {
+        match self {
+            Filter::Default(fallback) => value.or_else(|| Some(fallback.clone())),
+            Filter::Upper => value.map(|v| v.to_uppercase()),
+            Filter::Lower => value.map(|v| v.to_lowercase()),
+            Filter::Snake => value.map(|v| words(&v).join("_")),
+            Filter::Kebab => value.map(|v| words(&v).join("-")),
+            Filter::Join(component) => value.map(|v| join_path(&v, component)),
+            Filter::Basename => value.map(|v| basename(&v).to_string()),
+        }
+    }
+}
+
+/// Join a path component onto a base with exactly one `/`.
+fn join_path(base: &str, component: &str) -> String {
+    let base = base.trim_end_matches('/');
+    let component = component.trim_start_matches('/');
+    if base.is_empty() {
+        component.to_string()
+    } else {
+        format!("{base}/{component}")
+    }
+}
+
+/// Last component of a `/` or `\\` separated path.
+fn basename(path: &str) -> &str {
+    path.trim_end_matches(['/', '\\'])
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or_default()
+}
+
+/// Split a placeholder expression into the referenced name and its filters.
+pub(crate) fn parse_expression(expr: &str) -> Result<(String, Vec<Filter>), TemplateError> {
+    let mut segments = split_unquoted(expr, '|').into_iter();
+    let name = segments.next().unwrap_or_default().trim().to_string();
+    let filters = segments.map(Filter::parse).collect::<Result<_, _>>()?;
+    Ok((name, filters))
+}
+
+/// Split on `sep` outside of double-quoted strings.
+fn split_unquoted(s: &str, sep: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut in_quotes = false;
+    let mut escaped = false;
+    for (i, c) in s.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_quotes => escaped = true,
+            '"' => in_quotes = !in_quotes,
+            c if c == sep && !in_quotes => {
+                parts.push(&s[start..i]);
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&s[start..]);
+    parts
+}
+
+/// Parse a double-quoted filter argument.
+fn parse_string(s: &str) -> Result<String, TemplateError> {
+    let inner = s
+        .strip_prefix('"')
+        .and_then(|s| s.strip_suffix('"'))
+        .ok_or_else(|| TemplateError::InvalidArgument(s.to_string()))?;
+
+    let mut out = String::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(escaped) => out.push(escaped),
+                None => return Err(TemplateError::InvalidArgument(s.to_string())),
+            },
+            c => out.push(c),
+        }
+    }
+    Ok(out)
+}
+
+/// Split an identifier into lowercase words on separators and case changes.
+fn words(s: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut current = String::new();
+    let mut prev_lower = false;
+
+    for c in s.chars() {
+        if !c.is_alphanumeric() {
+            if !current.is_empty() {
+                words.push(std::mem::take(&mut current));
+            }
+            prev_lower = false;
+            continue;
+        }
+        if c.is_uppercase() && prev_lower && !current.is_empty() {
+            words.push(std::mem::take(&mut current));
+        }
+        prev_lower = c.is_lowercase() || c.is_numeric();
+        current.extend(c.to_lowercase());
+    }
+    if !current.is_empty() {
+        words.push(current);
+    }
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn case_filters() {
+        let value = Some("MyGame port-v2".to_string());
+        assert_eq!(
+            Filter::Snake.apply(value.clone()).unwrap(),
+            "my_game_port_v2"
+        );
+        assert_eq!(
+            Filter::Kebab.apply(value.clone()).unwrap(),
+            "my-game-port-v2"
+        );
+        assert_eq!(
+            Filter::Upper.apply(value.clone()).unwrap(),
+            "MYGAME PORT-V2"
+        );
+        assert_eq!(Filter::Lower.apply(value).unwrap(), "mygame port-v2");
+    }
+
+    #[test]
+    fn path_filters() {
+        let join = Filter::Join("raw".to_string());
+        assert_eq!(
+            join.apply(Some("./assets/".into())).unwrap(),
+            "./assets/raw"
+        );
+        assert_eq!(
+            Filter::Basename
+                .apply(Some("./dump/game.exe".into()))
+                .unwrap(),
+            "game.exe"
+        );
+        assert_eq!(Filter::Basename.apply(Some("a/b/".into())).unwrap(), "b");
+    }
+
+    #[test]
+    fn parse_filters() {
+        let (name, filters) = parse_expression(r#"out | default: "a|b" | upper"#).unwrap();
+        assert_eq!(name, "out");
+        assert_eq!(filters, [Filter::Default("a|b".to_string()), Filter::Upper]);
+
+        assert_eq!(
+            parse_expression("x | shout").unwrap_err(),
+            TemplateError::UnknownFilter("shout".to_string())
+        );
+        assert_eq!(
+            parse_expression("x | default").unwrap_err(),
+            TemplateError::FilterArgument("default".to_string())
+        );
+        assert_eq!(
+            parse_expression("x | upper: \"y\"").unwrap_err(),
+            TemplateError::FilterArgument("upper".to_string())
+        );
+    }
+}
@@ -0,0 +1,359 @@
+//! `{{placeholder}}` templating shared by manifests and seeds.
+//!
+//! A template is literal text with `{{name}}` placeholders. Whitespace inside
+//! the braces is ignored, and `\{{` produces a literal `{{`. Backslashes
+//! directly before `{{` pair up, so `\\{{name}}` is a literal `\` followed by
+//! the value of `name`.
+//!
+//! Placeholders can pipe their value through filters, e.g.
+//! `{{name | snake}}` or `{{out | default: "./build"}}`. See [`Filter`].
+
+mod filter;
+
+pub use filter::Filter;
+
+/// A parsed template.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+/// A piece of a template.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    /// Literal text (escapes already resolved).
+    Text(String),
+    /// A `{{...}}` placeholder.
+    Placeholder(Placeholder),
+}
+
+/// A single `{{...}}` placeholder.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Placeholder {
+    /// Name of the referenced value.
+    pub name: String,
+    /// Filters applied to the value, in order.
+    pub filters: Vec<Filter>,
+    /// The placeholder as written, including braces.
+    pub source: String,
+    /// Byte offset of the opening braces.
+    pub offset: usize,
+}
+
+/// Errors that can occur when parsing or rendering a template.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum TemplateError {
+    #[error("unclosed '{{{{' at offset {0}")]
+    Unclosed(usize),
+    #[error("empty placeholder at offset {0}")]
+    Empty(usize),
+    #[error("undefined variable '{0}'")]
+    Undefined(String),
+    #[error("variable '{0}' is not a string, number or bool and cannot be interpolated")]
+    NotInterpolable(String),
+    #[error("reference cycle: {}", .0.join(" -> "))]
+    Cycle(Vec<String>),
+    #[error("unknown filter '{0}'")]
+    UnknownFilter(String),
+    #[error("wrong arguments for filter '{0}'")]
+    FilterArgument(String),
+    #[error("invalid filter argument {0} (expected a double-quoted string)")]
+    InvalidArgument(String),
+    #[error("environment variable '{0}' is not allowed (add it to allow_env in [myenv])")]
+    EnvNotAllowed(String),
+    #[error("command '{0}' is not allowed (add it to allow_commands in [myenv])")]
+    CommandNotAllowed(String),
+    #[error("command '{command}' failed: {message}")]
+    Command { command: String, message: String },
+}
+
+impl Placeholder {
+    /// Apply this placeholder's filters to a looked-up value.
+    pub fn apply(&self, value: Option<String>) -> Option<String> {
+        self.filters
+            .iter()
+            .fold(value, |value, filter| filter.apply(value))
+    }
+
+    /// Whether every filter is a `default`, so the value passes through
+    /// unchanged when defined.
+    pub fn is_passthrough(&self) -> bool {
+        self.filters.iter().all(|f| matches!(f, Filter::Default(_)))
+    }
+}
+
+/// Find the closing `}}` of a placeholder, skipping quoted filter arguments.
+fn find_close(s: &str) -> Option<usize> {
+    let mut in_quotes = false;
+    let mut escaped = false;
+    for (i, c) in s.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_quotes => escaped = true,
+            '"' => in_quotes = !in_quotes,
+            '}' if !in_quotes && s[i..].starts_with("}}") => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Whether a placeholder name is made only of letters, digits, `_` and `-`.
+fn is_plain_name(name: &str) -> bool {
+    name.chars()
+        .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+}
+
+impl Template {
+    /// Parse a template string.
+    pub fn parse(s: &str) -> Result<Self, TemplateError> {
+        Self::parse_with(s, false)
+    }
+
+    /// Parse a template string, keeping any `{{` that doesn't start a
+    /// well-formed placeholder with a plain name (letters, digits, `_` and
+    /// `-`) as literal text.
+    ///
+    /// Suits files that use braces for something else, such as
+    /// `${{ secrets.TOKEN }}` in a GitHub workflow.
+    pub fn parse_loose(s: &str) -> Self {
+        Self::parse_with(s, true).expect("loose parsing never fails")
+    }
+
+    fn parse_with(s: &str, loose: bool) -> Result<Self, TemplateError> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut rest = s;
+        let mut offset = 0;
+
+        while let Some(pos) = rest.find("{{") {
+            // `\\` before `{{` is a literal `\`, and an unpaired `\{{` is a
+            // literal `{{`
+            let before = &rest[..pos];
+            let slashes = before.len() - before.trim_end_matches('\\').len();
+            text.push_str(&before[..pos - slashes]);
+            text.push_str(&"\\".repeat(slashes / 2));
+            if slashes % 2 == 1 {
+                text.push_str("{{");
+                rest = &rest[pos + 2..];
+                offset += pos + 2;
+                continue;
+            }
+
+            let start = offset + pos;
+            let after = &rest[pos + 2..];
+            let parsed = find_close(after)
+                .ok_or(TemplateError::Unclosed(start))
+                .and_then(|end| {
+                    let (name, filters) = filter::parse_expression(&after[..end])?;
+                    if name.is_empty() {
+                        return Err(TemplateError::Empty(start));
+                    }
+                    Ok((end, name, filters))
+                });
+            let (end, name, filters) = match parsed {
+                Ok(parsed) if !loose || is_plain_name(&parsed.1) => parsed,
+                Err(e) if !loose => return Err(e),
+                // Not a placeholder; keep the braces as text
+                _ => {
+                    text.push_str("{{");
+                    rest = after;
+                    offset = start + 2;
+                    continue;
+                }
+            };
+
+            if !text.is_empty() {
+                parts.push(Part::Text(std::mem::take(&mut text)));
+            }
+            parts.push(Part::Placeholder(Placeholder {
+                name,
+                filters,
+                source: rest[pos..pos + 2 + end + 2].to_string(),
+                offset: start,
+            }));
+
+            rest = &after[end + 2..];
+            offset = start + 2 + end + 2;
+        }
+
+        text.push_str(rest);
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+
+        Ok(Self { parts })
+    }
+
+    /// All placeholders in the template, in order.
+    pub fn placeholders(&self) -> impl Iterator<Item = &Placeholder> {
+        self.parts.iter().filter_map(|part| match part {
+            Part::Placeholder(p) => Some(p),
+            Part::Text(_) => None,
+        })
+    }
+
+    /// If the template is exactly one placeholder with no surrounding text,
+    /// return it.
+    pub fn as_placeholder(&self) -> Option<&Placeholder> {
+        match self.parts.as_slice() {
+            [Part::Placeholder(p)] => Some(p),
+            _ => None,
+        }
+    }
+
+    /// Whether the template contains any placeholders.
+    pub fn has_placeholders(&self) -> bool {
+        self.placeholders().next().is_some()
+    }
+
+    /// Render the template, failing on the first undefined placeholder.
+    pub fn render<F>(&self, mut lookup: F) -> Result<String, TemplateError>
+    where
+        F: FnMut(&str) -> Option<String>,
+    {
+        let mut out = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => out.push_str(text),
+                Part::Placeholder(p) => {
+                    let value = p
+                        .apply(lookup(&p.name))
+                        .ok_or_else(|| TemplateError::Undefined(p.name.clone()))?;
+                    out.push_str(&value);
+                }
+            }
+        }
+        Ok(out)
+    }
+
+    /// Render the template, leaving undefined placeholders as written.
+    pub fn render_lenient<F>(&self, mut lookup: F) -> String
+    where
+        F: FnMut(&str) -> Option<String>,
+    {
+        let mut out = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => out.push_str(text),
+                Part::Placeholder(p) => match p.apply(lookup(&p.name)) {
+                    Some(value) => out.push_str(&value),
+                    None => out.push_str(&p.source),
+                },
+            }
+        }
+        out
+    }
+}
+
+/// Parse and render a template in one step.
+pub fn render<F>(template: &str, lookup: F) -> Result<String, TemplateError>
+where
+    F: FnMut(&str) -> Option<String>,
+{
+    Template::parse(template)?.render(lookup)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn vars() -> HashMap<&'static str, &'static str> {
+        HashMap::from([("name", "demo"), ("dir", "./assets")])
+    }
+
+    fn lookup(name: &str) -> Option<String> {
+        vars().get(name).map(|v| v.to_string())
+    }
+
+    #[test]
+    fn render_placeholders() {
+        let out = render("{{dir}}/{{ name }}.toml", lookup).unwrap();
+        assert_eq!(out, "./assets/demo.toml");
+    }
+
+    #[test]
+    fn undefined_is_an_error() {
+        let err = render("{{dir}}/{{typo}}", lookup).unwrap_err();
+        assert_eq!(err, TemplateError::Undefined("typo".to_string()));
+    }
+
+    #[test]
+    fn lenient_keeps_undefined() {
+        let template = Template::parse("{{dir}}/{{ typo }}").unwrap();
+        assert_eq!(template.render_lenient(lookup), "./assets/{{ typo }}");
+    }
+
+    #[test]
+    fn escaped_braces_are_literal() {
+        let out = render(r"\{{name}} is {{name}}", lookup).unwrap();
+        assert_eq!(out, "{{name}} is demo");
+    }
+
+    #[test]
+    fn escaped_backslash_before_placeholder() {
+        let out = render(r"C:\\{{name}}", lookup).unwrap();
+        assert_eq!(out, r"C:\demo");
+
+        let out = render(r"\\\{{name}} and a\b", lookup).unwrap();
+        assert_eq!(out, r"\{{name}} and a\b");
+    }
+
+    #[test]
+    fn malformed_placeholders() {
+        assert_eq!(
+            Template::parse("a {{name").unwrap_err(),
+            TemplateError::Unclosed(2)
+        );
+        assert_eq!(
+            Template::parse("a {{ }}").unwrap_err(),
+            TemplateError::Empty(2)
+        );
+    }
+
+    #[test]
+    fn loose_keeps_foreign_braces() {
+        let template =
+            Template::parse_loose("${{ secrets.TOKEN }} {{name}} ${{ a || b }} {{ }} {{dir");
+        assert_eq!(
+            template.render(lookup).unwrap(),
+            "${{ secrets.TOKEN }} demo ${{ a || b }} {{ }} {{dir"
+        );
+
+        let err = Template::parse_loose("{{typo}}")
+            .render(lookup)
+            .unwrap_err();
+        assert_eq!(err, TemplateError::Undefined("typo".to_string()));
+    }
+
+    #[test]
+    fn filters_and_defaults() {
+        let out = render(
+            r#"{{name | upper}} {{out | default: "./build" | join: "web"}} {{dir | basename}}"#,
+            lookup,
+        )
+        .unwrap();
+        assert_eq!(out, "DEMO ./build/web assets");
+
+        // A quoted default may contain closing braces
+        let out = render(r#"{{missing | default: "}}"}}"#, lookup).unwrap();
+        assert_eq!(out, "}}");
+
+        let err = render("{{missing | upper}}", lookup).unwrap_err();
+        assert_eq!(err, TemplateError::Undefined("missing".to_string()));
+    }
+
+    #[test]
+    fn single_placeholder() {
+        let template = Template::parse("{{ name }}").unwrap();
+        assert_eq!(template.as_placeholder().unwrap().name, "name");
+        assert!(
+            Template::parse("x{{name}}")
+                .unwrap()
+                .as_placeholder()
+                .is_none()
+        );
+        assert!(!Template::parse("plain").unwrap().has_placeholders());
+    }
+}